@@ -26,8 +26,25 @@ cd \$(dirname "\$0")
 while : ; do
 
 ./nextui-updater 2>&1 >> logs.txt
-
-[[ \$? -eq 5 ]] || break
+STATUS=\$?
+
+# A self-updated binary which exits before showing its UI leaves
+# self-update.pending behind: restore the previous binary and start it again
+if [[ -f self-update.pending && -f nextui-updater.bak && \$STATUS -ne 5 ]]; then
+    echo "New updater failed to start (\$STATUS), rolling back" >> logs.txt
+    mv nextui-updater.bak nextui-updater
+    mv self-update.pending self-update.failed
+    continue
+fi
+
+# 6: reboot requested by the updater (--reboot exit)
+if [[ \$STATUS -eq 6 ]]; then
+    sync
+    reboot
+fi
+
+# 5: restart after a self-update
+[[ \$STATUS -eq 5 ]] || break
 
 done
 
@@ -1,27 +1,253 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use parking_lot::Mutex;
 
+use crate::battery::BatteryStatus;
+use crate::device::Device;
+use crate::file_picker::FilePicker;
 use crate::github::{Release, ReleaseAndTag, Tag};
+use crate::network::NetworkStatus;
+use crate::platform::Platform;
+use crate::profiles::Profile;
+use crate::reboot::RebootStrategy;
+use crate::settings::{ModifiedFilesPolicy, Settings, SettingsField};
+use crate::update::StagedUpdate;
+use crate::version::InstalledVersion;
 
 // Application state shared between UI thread and update thread
 #[derive(Clone)]
 pub enum Progress {
     Indeterminate,
     Determinate(f32),
+    Transfer(TransferProgress),
 }
 
+// Progress of a byte transfer such as a download
+#[derive(Clone, Copy, Debug)]
+pub struct TransferProgress {
+    pub done: u64,
+    // None if the size isn't known up front (no Content-Length)
+    pub total: Option<u64>,
+    pub bytes_per_second: f64,
+}
+
+impl TransferProgress {
+    pub fn fraction(&self) -> Option<f32> {
+        let total = self.total.filter(|&t| t > 0)?;
+        Some((self.done as f64 / total as f64).min(1.0) as f32)
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        let remaining = self.total?.saturating_sub(self.done);
+        (self.bytes_per_second > 0.0)
+            .then(|| Duration::from_secs_f64(remaining as f64 / self.bytes_per_second))
+    }
+
+    // e.g. "34.2 / 120.5 MB · 1.8 MB/s · 0:47 left"
+    pub fn summary(&self) -> String {
+        let mut parts = vec![];
+
+        if let Some(total) = self.total {
+            let (divisor, unit) = byte_unit(total as f64);
+            parts.push(format!(
+                "{:.1} / {:.1} {unit}",
+                self.done as f64 / divisor,
+                total as f64 / divisor
+            ));
+        } else {
+            let (divisor, unit) = byte_unit(self.done as f64);
+            parts.push(format!("{:.1} {unit}", self.done as f64 / divisor));
+        }
+
+        if self.bytes_per_second > 0.0 {
+            let (divisor, unit) = byte_unit(self.bytes_per_second);
+            parts.push(format!("{:.1} {unit}/s", self.bytes_per_second / divisor));
+        }
+
+        if let Some(eta) = self.eta() {
+            let secs = eta.as_secs();
+            if secs >= 3600 {
+                parts.push(format!(
+                    "{}:{:02}:{:02} left",
+                    secs / 3600,
+                    secs / 60 % 60,
+                    secs % 60
+                ));
+            } else {
+                parts.push(format!("{}:{:02} left", secs / 60, secs % 60));
+            }
+        }
+
+        parts.join(" · ")
+    }
+}
+
+// e.g. "120.5 MB"
+pub fn format_bytes(bytes: u64) -> String {
+    let (unit, suffix) = byte_unit(bytes as f64);
+    format!("{:.1} {suffix}", bytes as f64 / unit)
+}
+
+fn byte_unit(bytes: f64) -> (f64, &'static str) {
+    const KB: f64 = 1024.0;
+    const MB: f64 = 1024.0 * 1024.0;
+    if bytes >= MB {
+        (MB, "MB")
+    } else {
+        (KB, "KB")
+    }
+}
+
+// Steps of a multi-step operation such as an update
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    FetchMetadata,
+    Download,
+    Verify,
+    Backup,
+    Extract,
+    Finalize,
+    Reboot,
+}
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::FetchMetadata => "Fetch metadata",
+            Phase::Download => "Download",
+            Phase::Verify => "Verify",
+            Phase::Backup => "Back up",
+            Phase::Extract => "Extract",
+            Phase::Finalize => "Finalize",
+            Phase::Reboot => "Reboot",
+        }
+    }
+
+    // Rough share of the total time, used for the overall progress bar
+    pub fn weight(self) -> f32 {
+        match self {
+            Phase::Download => 50.0,
+            Phase::Extract => 30.0,
+            Phase::Verify | Phase::Backup => 6.0,
+            Phase::FetchMetadata | Phase::Finalize => 3.0,
+            Phase::Reboot => 2.0,
+        }
+    }
+}
+
+// The phases of the running operation and which one is active
+#[derive(Clone, Debug)]
+pub struct Pipeline {
+    phases: Vec<Phase>,
+    current: usize,
+}
+
+pub struct PipelineProgress {
+    // 1-based
+    pub step: usize,
+    pub steps: usize,
+    pub phase: Phase,
+    pub overall: f32,
+}
+
+impl Pipeline {
+    fn progress(&self, phase_fraction: f32) -> PipelineProgress {
+        let total: f32 = self.phases.iter().map(|p| p.weight()).sum();
+        let done: f32 = self.phases[..self.current].iter().map(|p| p.weight()).sum();
+        let phase = self.phases[self.current];
+
+        PipelineProgress {
+            step: self.current + 1,
+            steps: self.phases.len(),
+            phase,
+            overall: ((done + phase.weight() * phase_fraction.clamp(0.0, 1.0)) / total)
+                .clamp(0.0, 1.0),
+        }
+    }
+}
+
+// Shared flag used to ask a running operation to stop at the next safe point
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    // Err(Cancelled) once cancellation was requested, for use with `?`
+    pub fn check(&self) -> crate::Result<()> {
+        if self.is_cancelled() {
+            Err(Box::new(Cancelled))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// Error returned by operations stopped through a CancellationToken
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cancelled by user")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+#[allow(clippy::struct_excessive_bools)]
 pub struct AppState {
     submenu: Submenu,
-    current_version: Option<String>,
+    // Where a prompt returns to once answered
+    prompted_from: Submenu,
+    settings: Settings,
+    // Option focused in the settings screen
+    settings_focus: Option<SettingsField>,
+    profiles: Vec<Profile>,
+    current_version: Option<InstalledVersion>,
+    platform: Option<Platform>,
+    device: Option<Device>,
+    battery: Option<BatteryStatus>,
+    reboot_strategy: RebootStrategy,
+    // Installing without anyone to answer prompts, e.g. at boot
+    unattended: bool,
+    staged_update: Option<StagedUpdate>,
+    network: Option<NetworkStatus>,
+    // NextUI archive found on the SD card, offered while offline
+    sd_card_archive: Option<PathBuf>,
     nextui_release: Option<Release>,
     nextui_tag: Option<Tag>,
     nextui_releases_and_tags: Option<Vec<ReleaseAndTag>>,
     nextui_releases_and_tags_index: Option<usize>,
+    nextui_releases_next_page: Option<String>,
+    loading_more_releases: bool,
+    resolving_release: bool,
+    // Releases whose commit couldn't be looked up, not retried until reconnecting
+    unresolved_releases: Vec<String>,
     release_selection_menu: bool,
     release_selection_confirmed: bool,
     current_operation: Option<String>,
     progress: Option<Progress>,
+    pipeline: Option<Pipeline>,
+    // Set while the current operation can be cancelled
+    cancellation: Option<CancellationToken>,
+    // Install plan awaiting confirmation, and the user's answer
+    install_plan: Option<InstallPlan>,
+    install_plan_choice: Option<ModifiedFilesPolicy>,
+    file_picker: Option<FilePicker>,
+    // Question awaiting an answer, and the index of the chosen answer
+    confirmation: Option<Confirmation>,
+    confirmation_answer: Option<usize>,
     error: Option<String>,
     hint: Option<String>,
     should_quit: bool,
@@ -30,6 +256,31 @@ pub struct AppState {
 #[derive(Clone, Copy)]
 pub enum Submenu {
     NextUI,
+    InstallPlan,
+    FilePicker,
+    Confirmation,
+    Offline,
+    Settings,
+}
+
+// A question asked during an operation, answered with one of `choices`. The
+// first choice is focused, so it should be the safe one.
+#[derive(Clone, Debug, Default)]
+pub struct Confirmation {
+    pub message: String,
+    pub choices: Vec<String>,
+}
+
+// Summary of the changes an update is about to make, shown before any is made
+#[derive(Clone, Debug, Default)]
+pub struct InstallPlan {
+    pub install: usize,
+    // Files the user modified since they were installed
+    pub modified: Vec<String>,
+    // What happens to the modified files, None if the user has to choose
+    pub modified_policy: Option<ModifiedFilesPolicy>,
+    // Files dropped upstream, which will be removed
+    pub remove: Vec<String>,
 }
 
 pub struct AppStateManager {
@@ -41,15 +292,38 @@ impl AppStateManager {
         Self {
             state: Arc::new(Mutex::new(AppState {
                 submenu: Submenu::NextUI,
+                prompted_from: Submenu::NextUI,
+                settings: Settings::default(),
+                settings_focus: None,
+                profiles: Profile::defaults(),
                 current_version: None,
+                platform: None,
+                device: None,
+                battery: None,
+                reboot_strategy: RebootStrategy::Noop,
+                unattended: false,
+                staged_update: None,
+                network: None,
+                sd_card_archive: None,
                 nextui_release: None,
                 nextui_tag: None,
                 nextui_releases_and_tags: None,
                 nextui_releases_and_tags_index: None,
+                nextui_releases_next_page: None,
+                loading_more_releases: false,
+                resolving_release: false,
+                unresolved_releases: vec![],
                 release_selection_menu: false,
                 release_selection_confirmed: false,
                 current_operation: None,
                 progress: None,
+                pipeline: None,
+                cancellation: None,
+                install_plan: None,
+                install_plan_choice: None,
+                file_picker: None,
+                confirmation: None,
+                confirmation_answer: None,
                 error: None,
                 hint: None,
                 should_quit: false,
@@ -69,6 +343,18 @@ impl AppStateManager {
         self.state.lock().submenu
     }
 
+    pub fn settings(&self) -> Settings {
+        self.state.lock().settings.clone()
+    }
+
+    pub fn settings_focus(&self) -> Option<SettingsField> {
+        self.state.lock().settings_focus
+    }
+
+    pub fn profiles(&self) -> Vec<Profile> {
+        self.state.lock().profiles.clone()
+    }
+
     pub fn should_quit(&self) -> bool {
         self.state.lock().should_quit
     }
@@ -81,6 +367,20 @@ impl AppStateManager {
         self.state.lock().progress.clone()
     }
 
+    pub fn pipeline_progress(&self) -> Option<PipelineProgress> {
+        let state = self.state.lock();
+        let phase_fraction = match &state.progress {
+            Some(Progress::Determinate(pr)) => *pr,
+            Some(Progress::Transfer(transfer)) => transfer.fraction().unwrap_or(0.0),
+            Some(Progress::Indeterminate) | None => 0.0,
+        };
+        Some(state.pipeline.as_ref()?.progress(phase_fraction))
+    }
+
+    pub fn is_cancellable(&self) -> bool {
+        self.state.lock().cancellation.is_some()
+    }
+
     pub fn error(&self) -> Option<String> {
         self.state.lock().error.clone()
     }
@@ -89,10 +389,54 @@ impl AppStateManager {
         self.state.lock().hint.clone()
     }
 
-    pub fn current_version(&self) -> Option<String> {
+    pub fn install_plan(&self) -> Option<InstallPlan> {
+        self.state.lock().install_plan.clone()
+    }
+
+    pub fn file_picker(&self) -> Option<FilePicker> {
+        self.state.lock().file_picker.clone()
+    }
+
+    pub fn current_version(&self) -> Option<InstalledVersion> {
         self.state.lock().current_version.clone()
     }
 
+    pub fn platform(&self) -> Option<Platform> {
+        self.state.lock().platform
+    }
+
+    pub fn device(&self) -> Option<Device> {
+        self.state.lock().device.clone()
+    }
+
+    pub fn battery(&self) -> Option<BatteryStatus> {
+        self.state.lock().battery
+    }
+
+    pub fn reboot_strategy(&self) -> RebootStrategy {
+        self.state.lock().reboot_strategy
+    }
+
+    pub fn unattended(&self) -> bool {
+        self.state.lock().unattended
+    }
+
+    pub fn staged_update(&self) -> Option<StagedUpdate> {
+        self.state.lock().staged_update.clone()
+    }
+
+    pub fn network(&self) -> Option<NetworkStatus> {
+        self.state.lock().network
+    }
+
+    pub fn sd_card_archive(&self) -> Option<PathBuf> {
+        self.state.lock().sd_card_archive.clone()
+    }
+
+    pub fn confirmation(&self) -> Option<Confirmation> {
+        self.state.lock().confirmation.clone()
+    }
+
     pub fn nextui_release(&self) -> Option<Release> {
         self.state.lock().nextui_release.clone()
     }
@@ -109,6 +453,26 @@ impl AppStateManager {
         self.state.lock().nextui_releases_and_tags_index
     }
 
+    pub fn nextui_releases_next_page(&self) -> Option<String> {
+        self.state.lock().nextui_releases_next_page.clone()
+    }
+
+    pub fn loading_more_releases(&self) -> bool {
+        self.state.lock().loading_more_releases
+    }
+
+    pub fn resolving_release(&self) -> bool {
+        self.state.lock().resolving_release
+    }
+
+    pub fn release_unresolved(&self, tag_name: &str) -> bool {
+        self.state
+            .lock()
+            .unresolved_releases
+            .iter()
+            .any(|t| t == tag_name)
+    }
+
     pub fn release_selection_menu(&self) -> bool {
         self.state.lock().release_selection_menu
     }
@@ -122,6 +486,18 @@ impl AppStateManager {
         self.state.lock().submenu = submenu;
     }
 
+    pub fn set_settings(&self, settings: Settings) {
+        self.state.lock().settings = settings;
+    }
+
+    pub fn set_settings_focus(&self, field: Option<SettingsField>) {
+        self.state.lock().settings_focus = field;
+    }
+
+    pub fn set_profiles(&self, profiles: Vec<Profile>) {
+        self.state.lock().profiles = profiles;
+    }
+
     pub fn set_should_quit(&self, should_quit: bool) {
         self.state.lock().should_quit = should_quit;
     }
@@ -142,10 +518,42 @@ impl AppStateManager {
         self.state.lock().hint = hint;
     }
 
-    pub fn set_current_version(&self, version: Option<String>) {
+    pub fn set_current_version(&self, version: Option<InstalledVersion>) {
         self.state.lock().current_version = version;
     }
 
+    pub fn set_platform(&self, platform: Option<Platform>) {
+        self.state.lock().platform = platform;
+    }
+
+    pub fn set_device(&self, device: Option<Device>) {
+        self.state.lock().device = device;
+    }
+
+    pub fn set_battery(&self, battery: Option<BatteryStatus>) {
+        self.state.lock().battery = battery;
+    }
+
+    pub fn set_reboot_strategy(&self, reboot_strategy: RebootStrategy) {
+        self.state.lock().reboot_strategy = reboot_strategy;
+    }
+
+    pub fn set_unattended(&self, unattended: bool) {
+        self.state.lock().unattended = unattended;
+    }
+
+    pub fn set_staged_update(&self, staged_update: Option<StagedUpdate>) {
+        self.state.lock().staged_update = staged_update;
+    }
+
+    pub fn set_sd_card_archive(&self, archive: Option<PathBuf>) {
+        self.state.lock().sd_card_archive = archive;
+    }
+
+    pub fn set_network(&self, network: Option<NetworkStatus>) {
+        self.state.lock().network = network;
+    }
+
     pub fn set_nextui_release(&self, release: Option<Release>) {
         self.state.lock().nextui_release = release;
     }
@@ -162,6 +570,29 @@ impl AppStateManager {
         self.state.lock().nextui_releases_and_tags_index = releases_and_tags_index;
     }
 
+    pub fn set_nextui_releases_next_page(&self, next_page: Option<String>) {
+        self.state.lock().nextui_releases_next_page = next_page;
+    }
+
+    pub fn set_loading_more_releases(&self, loading: bool) {
+        self.state.lock().loading_more_releases = loading;
+    }
+
+    pub fn set_resolving_release(&self, resolving: bool) {
+        self.state.lock().resolving_release = resolving;
+    }
+
+    pub fn mark_release_unresolved(&self, tag_name: &str) {
+        self.state
+            .lock()
+            .unresolved_releases
+            .push(tag_name.to_string());
+    }
+
+    pub fn clear_unresolved_releases(&self) {
+        self.state.lock().unresolved_releases.clear();
+    }
+
     pub fn set_release_selection_menu(&self, release_selection_menu: bool) {
         self.state.lock().release_selection_menu = release_selection_menu;
     }
@@ -187,10 +618,28 @@ impl AppStateManager {
         self.state.lock().progress = Some(Progress::Determinate(progress));
     }
 
+    pub fn update_transfer_progress(&self, progress: TransferProgress) {
+        self.state.lock().progress = Some(Progress::Transfer(progress));
+    }
+
+    // Forget the release list, e.g. when the source repository changed
+    pub fn clear_nextui_releases(&self) {
+        let mut state = self.state.lock();
+        state.nextui_release = None;
+        state.nextui_tag = None;
+        state.nextui_releases_and_tags = None;
+        state.nextui_releases_and_tags_index = None;
+        state.nextui_releases_next_page = None;
+        state.unresolved_releases.clear();
+        state.release_selection_menu = false;
+    }
+
     pub fn finish_operation(&self) {
         let mut state = self.state.lock();
         state.current_operation = None;
         state.progress = None;
+        state.pipeline = None;
+        state.cancellation = None;
     }
 
     pub fn set_operation_failed(&self, error_msg: &str) {
@@ -198,6 +647,155 @@ impl AppStateManager {
         state.current_operation = None;
         state.error = Some(error_msg.to_string());
         state.progress = None;
+        state.pipeline = None;
+        state.cancellation = None;
+    }
+
+    // Start a multi-step operation, made of `phases` in order
+    pub fn start_pipeline(&self, phases: &[Phase]) {
+        self.state.lock().pipeline = Some(Pipeline {
+            phases: phases.to_vec(),
+            current: 0,
+        });
+    }
+
+    // Move the running pipeline on to `phase`, resetting the per-phase progress
+    pub fn enter_phase(&self, phase: Phase, operation: &str) {
+        let mut state = self.state.lock();
+        if let Some(pipeline) = &mut state.pipeline {
+            if let Some(index) = pipeline.phases.iter().position(|&p| p == phase) {
+                pipeline.current = index;
+            }
+        }
+        state.current_operation = Some(operation.to_string());
+        state.progress = Some(Progress::Indeterminate);
+    }
+
+    // Allow the user to cancel the current operation, until `end_cancellable`
+    pub fn begin_cancellable(&self) -> CancellationToken {
+        let token = CancellationToken::default();
+        self.state.lock().cancellation = Some(token.clone());
+        token
+    }
+
+    pub fn end_cancellable(&self) {
+        self.state.lock().cancellation = None;
+    }
+
+    pub fn cancel_operation(&self) {
+        if let Some(token) = &self.state.lock().cancellation {
+            println!("Cancellation requested");
+            token.cancel();
+        }
+    }
+
+    // Show `plan` and wait for the user to confirm it
+    pub fn show_install_plan(&self, plan: InstallPlan) {
+        let mut state = self.state.lock();
+        state.install_plan = Some(plan);
+        state.install_plan_choice = None;
+        state.progress = None;
+        state.prompted_from = state.submenu;
+        state.submenu = Submenu::InstallPlan;
+        state.hint = None;
+    }
+
+    // Confirm the install plan, with what to do about modified files
+    pub fn answer_install_plan(&self, choice: ModifiedFilesPolicy) {
+        self.state.lock().install_plan_choice = Some(choice);
+    }
+
+    pub fn take_install_plan_choice(&self) -> Option<ModifiedFilesPolicy> {
+        self.state.lock().install_plan_choice.take()
+    }
+
+    pub fn end_install_plan(&self) {
+        let mut state = self.state.lock();
+        state.install_plan = None;
+        state.install_plan_choice = None;
+        state.submenu = state.prompted_from;
+        state.hint = None;
+    }
+
+    // Ask the user a question, which the operation waits on
+    pub fn ask_confirmation(&self, confirmation: Confirmation) {
+        let mut state = self.state.lock();
+        state.confirmation = Some(confirmation);
+        state.confirmation_answer = None;
+        state.progress = None;
+        state.prompted_from = state.submenu;
+        state.submenu = Submenu::Confirmation;
+        state.hint = None;
+    }
+
+    pub fn answer_confirmation(&self, choice: usize) {
+        self.state.lock().confirmation_answer = Some(choice);
+    }
+
+    pub fn take_confirmation_answer(&self) -> Option<usize> {
+        self.state.lock().confirmation_answer.take()
+    }
+
+    pub fn end_confirmation(&self) {
+        let mut state = self.state.lock();
+        state.confirmation = None;
+        state.confirmation_answer = None;
+        state.submenu = state.prompted_from;
+        state.hint = None;
+    }
+
+    pub fn open_file_picker(&self, picker: FilePicker) {
+        let mut state = self.state.lock();
+        state.file_picker = Some(picker);
+        state.submenu = Submenu::FilePicker;
+        state.hint = None;
+    }
+
+    pub fn close_file_picker(&self) {
+        let mut state = self.state.lock();
+        state.file_picker = None;
+        state.submenu = Submenu::NextUI;
+        state.hint = None;
+    }
+
+    pub fn open_settings(&self) {
+        let mut state = self.state.lock();
+        state.settings_focus = None;
+        state.submenu = Submenu::Settings;
+        state.hint = None;
+    }
+
+    pub fn close_settings(&self) {
+        let mut state = self.state.lock();
+        state.settings_focus = None;
+        state.submenu = Submenu::NextUI;
+        state.hint = None;
+    }
+
+    // Show the offline screen, offering to install `archive` from the SD card
+    pub fn show_offline(&self, archive: Option<PathBuf>) {
+        let mut state = self.state.lock();
+        state.sd_card_archive = archive;
+        state.submenu = Submenu::Offline;
+        state.hint = None;
+    }
+
+    pub fn leave_offline(&self) {
+        let mut state = self.state.lock();
+        state.submenu = Submenu::NextUI;
+        state.hint = None;
+    }
+
+    pub fn update_file_picker(&self, update: impl FnOnce(&mut FilePicker)) {
+        if let Some(picker) = &mut self.state.lock().file_picker {
+            update(picker);
+        }
+    }
+
+    pub fn leave_release_selection_menu(&self) {
+        let mut state = self.state.lock();
+        state.release_selection_menu = false;
+        state.release_selection_confirmed = false;
     }
 
     pub fn clear_error(&self) {
@@ -0,0 +1,185 @@
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+use crate::app_state::AppStateManager;
+
+const DEFAULT_POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+// Overrides the power supply directory, e.g. to test against a fake one
+pub const POWER_SUPPLY_DIR_ENV: &str = "NEXTUI_UPDATER_POWER_SUPPLY";
+
+static POWER_SUPPLY_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatteryStatus {
+    // Charge in percent
+    pub capacity: u8,
+    pub charging: bool,
+}
+
+// Use `dir` instead of the sysfs power supply class, must be called before the
+// battery is first read
+pub fn set_power_supply_dir(dir: PathBuf) {
+    if POWER_SUPPLY_DIR.set(dir).is_err() {
+        println!("Power supply directory already set");
+    }
+}
+
+fn power_supply_dir() -> &'static Path {
+    POWER_SUPPLY_DIR.get_or_init(|| {
+        std::env::var_os(POWER_SUPPLY_DIR_ENV)
+            .map_or_else(|| PathBuf::from(DEFAULT_POWER_SUPPLY_DIR), PathBuf::from)
+    })
+}
+
+fn read_attribute(supply: &Path, name: &str) -> Option<String> {
+    std::fs::read_to_string(supply.join(name))
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+// Read the battery from its power supply node (type "Battery"), None on devices
+// without one. Charging also counts an online charger ("Mains" or "USB" supply).
+pub fn read() -> Option<BatteryStatus> {
+    read_from(power_supply_dir())
+}
+
+fn read_from(power_supply_dir: &Path) -> Option<BatteryStatus> {
+    let supplies: Vec<PathBuf> = std::fs::read_dir(power_supply_dir)
+        .ok()?
+        .filter_map(std::result::Result::ok)
+        .map(|entry| entry.path())
+        .collect();
+
+    let battery = supplies
+        .iter()
+        .find(|supply| read_attribute(supply, "type").as_deref() == Some("Battery"))?;
+    let capacity = read_attribute(battery, "capacity")?
+        .parse::<u8>()
+        .ok()?
+        .min(100);
+
+    let battery_charging = matches!(
+        read_attribute(battery, "status").as_deref(),
+        Some("Charging" | "Full")
+    );
+    let charger_online = supplies.iter().any(|supply| {
+        matches!(
+            read_attribute(supply, "type").as_deref(),
+            Some("Mains" | "USB")
+        ) && read_attribute(supply, "online").as_deref() == Some("1")
+    });
+
+    Some(BatteryStatus {
+        capacity,
+        charging: battery_charging || charger_online,
+    })
+}
+
+// Keep the battery status in the app state up to date
+pub fn watch(app_state: &'static AppStateManager) {
+    thread::spawn(move || loop {
+        app_state.set_battery(read());
+        thread::sleep(Duration::from_secs(10));
+    });
+}
+
+// Refuse to install when the battery might run out half-way
+pub fn check_for_install(min_capacity: u8) -> crate::Result<()> {
+    check_for_install_in(power_supply_dir(), min_capacity)
+}
+
+fn check_for_install_in(power_supply_dir: &Path, min_capacity: u8) -> crate::Result<()> {
+    match read_from(power_supply_dir) {
+        Some(status) if !status.charging && status.capacity < min_capacity => Err(format!(
+            "Battery at {}%, charge it to at least {min_capacity}% or plug in the charger to update",
+            status.capacity
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A power_supply directory with one node per (name, [(attribute, value)])
+    fn power_supply(supplies: &[(&str, &[(&str, &str)])]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, attributes) in supplies {
+            let supply = dir.path().join(name);
+            std::fs::create_dir(&supply).unwrap();
+            for (attribute, value) in *attributes {
+                std::fs::write(supply.join(attribute), format!("{value}\n")).unwrap();
+            }
+        }
+        dir
+    }
+
+    fn battery(capacity: &'static str, status: &'static str) -> [(&'static str, &'static str); 3] {
+        [
+            ("type", "Battery"),
+            ("capacity", capacity),
+            ("status", status),
+        ]
+    }
+
+    #[test]
+    fn reads_battery() {
+        let dir = power_supply(&[("axp2202-battery", &battery("80", "Discharging"))]);
+        assert_eq!(
+            read_from(dir.path()),
+            Some(BatteryStatus {
+                capacity: 80,
+                charging: false
+            })
+        );
+    }
+
+    #[test]
+    fn online_charger_counts_as_charging() {
+        let dir = power_supply(&[
+            ("battery", &battery("20", "Not charging")),
+            ("usb", &[("type", "USB"), ("online", "1")]),
+        ]);
+        assert!(read_from(dir.path()).unwrap().charging);
+
+        let dir = power_supply(&[
+            ("battery", &battery("20", "Discharging")),
+            ("usb", &[("type", "USB"), ("online", "0")]),
+        ]);
+        assert!(!read_from(dir.path()).unwrap().charging);
+    }
+
+    #[test]
+    fn missing_battery_node_reads_none() {
+        let dir = power_supply(&[("ac", &[("type", "Mains"), ("online", "1")])]);
+        assert_eq!(read_from(dir.path()), None);
+
+        // A battery without a capacity can't be judged either
+        let dir = power_supply(&[("battery", &[("type", "Battery")])]);
+        assert_eq!(read_from(dir.path()), None);
+
+        assert_eq!(read_from(&dir.path().join("missing")), None);
+    }
+
+    #[test]
+    fn refuses_install_on_low_battery() {
+        let dir = power_supply(&[("battery", &battery("10", "Discharging"))]);
+        let err = check_for_install_in(dir.path(), 30).unwrap_err();
+        assert!(err.to_string().contains("Battery at 10%"));
+
+        assert!(check_for_install_in(dir.path(), 10).is_ok());
+    }
+
+    #[test]
+    fn allows_install_while_charging_or_without_battery() {
+        let dir = power_supply(&[("battery", &battery("10", "Charging"))]);
+        assert!(check_for_install_in(dir.path(), 30).is_ok());
+
+        let dir = power_supply(&[]);
+        assert!(check_for_install_in(dir.path(), 30).is_ok());
+    }
+}
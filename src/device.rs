@@ -0,0 +1,147 @@
+use regex::Regex;
+
+use crate::github::{Release, ReleaseManifest};
+use crate::platform::Platform;
+
+// Handhelds known to run NextUI: id (as in NextUI's DEVICE variable and release
+// compatibility lists), model name and platform
+const KNOWN_DEVICES: [(&str, &str, Platform); 4] = [
+    ("brick", "TrimUI Brick", Platform::Tg5040),
+    ("smartpro", "TrimUI Smart Pro", Platform::Tg5040),
+    ("smartpros", "TrimUI Smart Pro S", Platform::Tg5050),
+    ("flip", "Miyoo Flip", Platform::My355),
+];
+
+// The handheld the updater runs on
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Device {
+    // None if only the platform is known
+    pub id: Option<String>,
+    pub model: String,
+    pub platform: Option<Platform>,
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+impl Device {
+    fn known(id: &str, model: &str, platform: Platform) -> Self {
+        Self {
+            id: Some(id.to_string()),
+            model: model.to_string(),
+            platform: Some(platform),
+        }
+    }
+
+    // Identify the device from the DEVICE variable set by the NextUI launcher or
+    // the device tree model, falling back to what the platform tells
+    pub fn detect(platform: Option<Platform>) -> Self {
+        let from_env = std::env::var("DEVICE").ok().and_then(|device| {
+            KNOWN_DEVICES
+                .iter()
+                .find(|(id, _, _)| *id == normalize(&device))
+        });
+
+        let from_device_tree = || {
+            let model = normalize(&std::fs::read_to_string("/proc/device-tree/model").ok()?);
+            // Longest names first, so "Smart Pro S" isn't taken for "Smart Pro"
+            let mut candidates: Vec<_> = KNOWN_DEVICES.iter().collect();
+            candidates.sort_by_key(|(_, name, _)| std::cmp::Reverse(name.len()));
+            candidates
+                .into_iter()
+                .find(|(_, name, _)| model.contains(&normalize(name)))
+        };
+
+        let device = match from_env.or_else(from_device_tree) {
+            Some((id, model, platform)) => Self::known(id, model, *platform),
+            None => match platform {
+                // Both the Brick and the Smart Pro are tg5040 devices
+                Some(Platform::Tg5040) => Self {
+                    id: None,
+                    model: "TrimUI Brick / Smart Pro".to_string(),
+                    platform,
+                },
+                Some(Platform::Tg5050) => {
+                    Self::known("smartpros", "TrimUI Smart Pro S", Platform::Tg5050)
+                }
+                Some(Platform::My355) => Self::known("flip", "Miyoo Flip", Platform::My355),
+                None => Self {
+                    id: None,
+                    model: "Unknown device".to_string(),
+                    platform: None,
+                },
+            },
+        };
+        println!("Detected device: {device:?}");
+        device
+    }
+
+    // e.g. "TrimUI Brick (tg5040)"
+    pub fn description(&self) -> String {
+        match self.platform {
+            Some(platform) => format!("{} ({})", self.model, platform.name()),
+            None => self.model.clone(),
+        }
+    }
+
+    // Whether a release supporting `supported` devices or platforms runs on this
+    // device, None if that can't be told
+    pub fn is_supported(&self, supported: &[String]) -> Option<bool> {
+        if supported.is_empty() {
+            return Some(true);
+        }
+
+        let supported: Vec<String> = supported.iter().map(|name| normalize(name)).collect();
+        if self
+            .platform
+            .is_some_and(|p| supported.contains(&p.name().to_string()))
+        {
+            return Some(true);
+        }
+        if let Some(id) = &self.id {
+            return Some(supported.contains(id));
+        }
+
+        // The model of a platform with several ones is unknown, so it's only
+        // certainly unsupported if none of them is supported
+        let platform = self.platform?;
+        let any_model_supported = KNOWN_DEVICES
+            .iter()
+            .filter(|(_, _, p)| *p == platform)
+            .any(|(id, _, _)| supported.iter().any(|s| s == id));
+        if any_model_supported {
+            None
+        } else {
+            Some(false)
+        }
+    }
+}
+
+// Devices or platforms a release supports, from its manifest or else from a
+// `<!-- devices: brick, tg5050 -->` marker in its release notes. Empty if the
+// release doesn't say.
+pub fn supported_devices(release: &Release, manifest: &ReleaseManifest) -> Vec<String> {
+    if !manifest.devices.is_empty() {
+        return manifest.devices.clone();
+    }
+
+    let marker_re = Regex::new(r"(?i)<!--\s*devices:\s*(?<devices>[^>]*?)\s*-->")
+        .expect("Failed to compile regex");
+    release
+        .body
+        .as_deref()
+        .and_then(|body| marker_re.captures(body))
+        .map(|captures| {
+            captures["devices"]
+                .split(',')
+                .map(str::trim)
+                .filter(|device| !device.is_empty())
+                .map(ToOwned::to_owned)
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,234 @@
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selection {
+    None,
+    Partial,
+    All,
+}
+
+// A visible row of the file picker tree
+#[derive(Clone, Debug)]
+pub struct PickerRow {
+    pub path: String,
+    pub name: String,
+    pub depth: usize,
+    pub is_folder: bool,
+    pub expanded: bool,
+    pub selection: Selection,
+    // In a location the release may not write, so it can't be ticked. Folders
+    // are protected if all their files are.
+    pub protected: bool,
+}
+
+// Contents of a release asset, browsed as a tree to pick the files to install
+#[derive(Clone, Debug, Default)]
+pub struct FilePicker {
+    pub asset: String,
+    // Path and size of every file, sorted by path
+    files: Vec<(String, u64)>,
+    expanded: BTreeSet<String>,
+    selected: BTreeSet<String>,
+    protected: BTreeSet<String>,
+    // Path of the row with focus, which Left/Right collapse and expand
+    pub focused: Option<String>,
+}
+
+// "a/b/c" -> ["a", "a/b"]
+fn parent_folders(path: &str) -> impl Iterator<Item = &str> {
+    path.match_indices('/').map(move |(end, _)| &path[..end])
+}
+
+impl FilePicker {
+    pub fn new(
+        asset: String,
+        mut files: Vec<(String, u64)>,
+        is_protected: impl Fn(&str) -> bool,
+    ) -> Self {
+        files.sort();
+        let protected = files
+            .iter()
+            .map(|(path, _)| path)
+            .filter(|path| is_protected(path))
+            .cloned()
+            .collect();
+        Self {
+            asset,
+            files,
+            protected,
+            ..Self::default()
+        }
+    }
+
+    fn files_in<'a>(&'a self, folder: &'a str) -> impl Iterator<Item = &'a String> {
+        self.files.iter().map(|(path, _)| path).filter(move |path| {
+            path.strip_prefix(folder)
+                .is_some_and(|p| p.starts_with('/'))
+        })
+    }
+
+    // Rows of the expanded part of the tree, folders before the files they contain
+    pub fn rows(&self) -> Vec<PickerRow> {
+        // Number of files that can be ticked and of ticked files within each folder
+        let mut counts: HashMap<&str, (usize, usize)> = HashMap::new();
+        for (path, _) in &self.files {
+            let selectable = usize::from(!self.protected.contains(path));
+            let selected = usize::from(self.selected.contains(path));
+            for folder in parent_folders(path) {
+                let count = counts.entry(folder).or_default();
+                count.0 += selectable;
+                count.1 += selected;
+            }
+        }
+
+        let mut rows = vec![];
+        let mut shown_folders = BTreeSet::new();
+        for (path, _) in &self.files {
+            let mut visible = true;
+            for (depth, folder) in parent_folders(path).enumerate() {
+                if visible && shown_folders.insert(folder) {
+                    let (total, selected) = counts[folder];
+                    rows.push(PickerRow {
+                        path: folder.to_string(),
+                        name: folder.rsplit('/').next().unwrap_or(folder).to_string(),
+                        depth,
+                        is_folder: true,
+                        expanded: self.expanded.contains(folder),
+                        selection: match selected {
+                            0 => Selection::None,
+                            _ if selected == total => Selection::All,
+                            _ => Selection::Partial,
+                        },
+                        protected: total == 0,
+                    });
+                }
+                visible &= self.expanded.contains(folder);
+            }
+
+            if visible {
+                rows.push(PickerRow {
+                    path: path.clone(),
+                    name: path.rsplit('/').next().unwrap_or(path).to_string(),
+                    depth: parent_folders(path).count(),
+                    is_folder: false,
+                    expanded: false,
+                    selection: if self.selected.contains(path) {
+                        Selection::All
+                    } else {
+                        Selection::None
+                    },
+                    protected: self.protected.contains(path),
+                });
+            }
+        }
+
+        rows
+    }
+
+    // Tick or untick a file, or every file in a folder, leaving protected files out
+    pub fn toggle(&mut self, path: &str) {
+        if self.protected.contains(path) {
+            return;
+        }
+        if self.files.iter().any(|(file, _)| file == path) {
+            if !self.selected.remove(path) {
+                self.selected.insert(path.to_string());
+            }
+            return;
+        }
+
+        let files: Vec<String> = self
+            .files_in(path)
+            .filter(|file| !self.protected.contains(*file))
+            .cloned()
+            .collect();
+        if files.iter().all(|file| self.selected.contains(file)) {
+            for file in &files {
+                self.selected.remove(file);
+            }
+        } else {
+            self.selected.extend(files);
+        }
+    }
+
+    // Expand or collapse the focused folder
+    pub fn expand_focused(&mut self, expanded: bool) {
+        let Some(focused) = self.focused.clone() else {
+            return;
+        };
+        if self.files.iter().any(|(file, _)| *file == focused) {
+            return;
+        }
+
+        if expanded {
+            self.expanded.insert(focused);
+        } else {
+            self.expanded.remove(&focused);
+        }
+    }
+
+    pub fn selected(&self) -> Vec<String> {
+        self.selected.iter().cloned().collect()
+    }
+
+    pub fn selected_size(&self) -> u64 {
+        self.files
+            .iter()
+            .filter(|(path, _)| self.selected.contains(path))
+            .map(|(_, size)| size)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn picker() -> FilePicker {
+        let files = [
+            "Bios/readme.txt",
+            "Bios/GBA/readme.txt",
+            "Tools/a*b.txt",
+            "Tools/ab.txt",
+        ]
+        .iter()
+        .map(|path| (path.to_string(), 1))
+        .collect();
+        FilePicker::new("all.zip".to_string(), files, |path| {
+            path.starts_with("Bios/")
+        })
+    }
+
+    fn row(picker: &FilePicker, path: &str) -> PickerRow {
+        picker
+            .rows()
+            .into_iter()
+            .find(|row| row.path == path)
+            .unwrap()
+    }
+
+    #[test]
+    fn protected_files_cant_be_ticked() {
+        let mut picker = picker();
+        picker.expanded.insert("Bios".to_string());
+
+        picker.toggle("Bios/readme.txt");
+        picker.toggle("Bios");
+        assert!(picker.selected().is_empty());
+        assert!(row(&picker, "Bios").protected);
+        assert!(row(&picker, "Bios/readme.txt").protected);
+        assert!(!row(&picker, "Tools").protected);
+    }
+
+    #[test]
+    fn folders_tick_their_files_exactly() {
+        let mut picker = picker();
+        picker.toggle("Tools/a*b.txt");
+        assert_eq!(picker.selected(), vec!["Tools/a*b.txt".to_string()]);
+        assert_eq!(row(&picker, "Tools").selection, Selection::Partial);
+
+        picker.toggle("Tools");
+        assert_eq!(picker.selected().len(), 2);
+        assert_eq!(row(&picker, "Tools").selection, Selection::All);
+    }
+}
@@ -4,11 +4,29 @@ use serde::Deserialize;
 pub struct Asset {
     pub name: String,
     pub url: String,
+    #[serde(default)]
+    pub size: u64,
+    // e.g. "sha256:<hex>"
+    #[serde(default)]
+    pub digest: Option<String>,
+    // Public github.com URL, which mirrors can proxy
+    #[serde(default)]
+    pub browser_download_url: Option<String>,
 }
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct Release {
     pub tag_name: String,
+    // Branch name or commit SHA the tag was created from
+    #[serde(default)]
+    pub target_commitish: String,
+    #[serde(default)]
+    pub published_at: Option<String>,
+    // Release notes (Markdown)
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub prerelease: bool,
     pub assets: Vec<Asset>,
 }
 
@@ -23,8 +41,43 @@ pub struct Commit {
     pub sha: String,
 }
 
+#[derive(Deserialize, Clone, Debug)]
+pub struct GitObject {
+    pub sha: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct GitRef {
+    pub object: GitObject,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct GitTag {
+    pub object: GitObject,
+}
+
+// Optional release asset describing the release beyond what the GitHub API provides
+pub const MANIFEST_ASSET_NAME: &str = "manifest.json";
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct ReleaseManifest {
+    // Commit the release was built from
+    #[serde(default)]
+    pub commit: Option<String>,
+    // Protected locations (e.g. "Bios/") the release may write to
+    #[serde(default)]
+    pub allow_protected: Vec<String>,
+    // Devices (e.g. "brick") or platforms (e.g. "tg5040") the release supports,
+    // all if empty
+    #[serde(default)]
+    pub devices: Vec<String>,
+}
+
 #[derive(Clone, Debug)]
 pub struct ReleaseAndTag {
     pub release: Release,
-    pub tag: Tag,
+    // None if the release's commit could not be resolved
+    pub tag: Option<Tag>,
 }
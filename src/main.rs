@@ -5,18 +5,40 @@
 #![allow(dead_code)]
 
 use app_state::AppStateManager;
+use device::Device;
+use platform::Platform;
+use profiles::Profile;
+use reboot::RebootStrategy;
+use settings::Settings;
 use std::env;
+use std::path::PathBuf;
 use std::thread;
 use ui::run_ui;
-use update::{do_nextui_release_check, do_self_update};
+use update::{
+    apply_staged_update, check_connection, do_nextui_release_check, do_self_update,
+    load_cached_nextui_releases, reconnected, set_tls_policy, StagedUpdate,
+};
+use version::InstalledVersion;
 
 mod app_state;
+mod atomic_write;
+mod battery;
+mod device;
+mod file_picker;
 mod github;
+mod network;
+mod platform;
+mod profiles;
+mod reboot;
+mod settings;
 mod ui;
 mod update;
+mod version;
 
 // Constants
 pub const SDCARD_ROOT: &str = "/mnt/SDCARD/";
+// Updater's own data (caches, settings etc.), relative to SDCARD_ROOT
+pub const UPDATER_DATA_DIR: &str = ".userdata/shared/updater/";
 
 // Error type for the application
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
@@ -38,24 +60,89 @@ fn parse_mock_display_size() -> Option<(u32, u32)> {
     None
 }
 
+// `--power-supply <dir>` reads the battery from a fake sysfs power supply directory
+fn parse_power_supply_dir() -> Option<PathBuf> {
+    let args: Vec<String> = env::args().collect();
+    args.iter()
+        .position(|arg| arg == "--power-supply")
+        .and_then(|i| args.get(i + 1))
+        .map(PathBuf::from)
+}
+
+// `--reboot <strategy>` overrides how the device is rebooted after an update
+fn parse_reboot_strategy() -> Option<RebootStrategy> {
+    let args: Vec<String> = env::args().collect();
+    let name = args
+        .iter()
+        .position(|arg| arg == "--reboot")
+        .and_then(|i| args.get(i + 1))?;
+
+    let strategy = RebootStrategy::from_name(name);
+    if strategy.is_none() {
+        println!("Ignoring unknown reboot strategy: {name}");
+    }
+    strategy
+}
+
 fn main() -> Result<()> {
+    // Checked by a running updater before it switches to this one
+    if env::args().any(|arg| arg == "--version") {
+        println!("{}", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+
     // Initialize application state
     let app_state: &'static AppStateManager = Box::leak(Box::new(AppStateManager::new()));
 
+    let settings = Settings::load();
+    set_tls_policy(settings.tls);
+    app_state.set_settings(settings);
+    app_state.set_profiles(Profile::load_all());
+
     // Get current NextUI version
-    let version_file =
-        std::fs::read_to_string(SDCARD_ROOT.to_owned() + ".system/version.txt").unwrap_or_default();
-    let current_sha = version_file
-        .lines()
-        .nth(1)
-        .map(std::borrow::ToOwned::to_owned);
-    app_state.set_current_version(current_sha);
-
-    // Self-update
+    let current_version = InstalledVersion::load();
+    let device = Device::detect(Platform::detect(current_version.as_ref()));
+    app_state.set_platform(device.platform);
+    app_state.set_reboot_strategy(
+        parse_reboot_strategy().unwrap_or_else(|| RebootStrategy::default_for(device.platform)),
+    );
+    app_state.set_device(Some(device));
+    app_state.set_current_version(current_version);
+
+    if let Some(dir) = parse_power_supply_dir() {
+        battery::set_power_supply_dir(dir);
+    }
+    app_state.set_staged_update(StagedUpdate::load());
+
+    // Install a staged update from the boot hook, without showing the UI
+    if env::args().any(|arg| arg == "--apply-staged") {
+        if app_state.staged_update().is_none() {
+            println!("No staged update to apply");
+            return Ok(());
+        }
+        app_state.set_unattended(true);
+        return apply_staged_update(app_state);
+    }
+
+    battery::watch(app_state);
+    network::watch(app_state, move || reconnected(app_state));
+
+    // Self-update, unless turned off (e.g. for devices pinned to an updater version)
+    let self_update =
+        app_state.settings().self_update && !env::args().any(|arg| arg == "--no-self-update");
+    if !self_update {
+        println!("Self-update disabled");
+    }
+
     let app_state_clone = app_state.clone();
     thread::spawn(move || {
-        do_self_update(&app_state_clone);
-        do_nextui_release_check(&app_state_clone);
+        load_cached_nextui_releases(&app_state_clone);
+        if check_connection(&app_state_clone) {
+            if self_update {
+                do_self_update(&app_state_clone);
+            }
+            do_nextui_release_check(&app_state_clone);
+        }
     });
 
     // Parse mock display size from arguments
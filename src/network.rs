@@ -0,0 +1,149 @@
+use std::net::ToSocketAddrs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use crate::app_state::AppStateManager;
+use crate::settings::TlsPolicy;
+
+// Host the releases are fetched from
+const RELEASE_HOST: &str = "api.github.com";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetworkStatus {
+    // No network interface is up, e.g. Wi-Fi is turned off
+    NoInterface,
+    // Connected to a network, but the release host can't be resolved
+    NoDns,
+    // The release host resolves but doesn't answer
+    Unreachable,
+    // Wi-Fi signal in percent, if known
+    Online { signal: Option<u8> },
+}
+
+impl NetworkStatus {
+    pub fn is_online(self) -> bool {
+        matches!(self, Self::Online { .. })
+    }
+
+    // Shown on the offline screen
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::NoInterface => "Wi-Fi is turned off or not connected",
+            Self::NoDns => "Connected to Wi-Fi, but there is no internet access",
+            Self::Unreachable => "GitHub can't be reached right now",
+            Self::Online { .. } => "Connected",
+        }
+    }
+}
+
+fn read_attribute(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+// Whether any interface other than loopback is up
+fn interface_up() -> bool {
+    let Ok(interfaces) = std::fs::read_dir("/sys/class/net") else {
+        return false;
+    };
+
+    interfaces
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| entry.file_name() != "lo")
+        .any(|entry| {
+            let path = entry.path();
+            match read_attribute(&path.join("operstate")).as_deref() {
+                Some("up") => true,
+                // Some Wi-Fi drivers don't report their state, the carrier tells
+                Some("unknown") => read_attribute(&path.join("carrier")).as_deref() == Some("1"),
+                _ => false,
+            }
+        })
+}
+
+// Link quality of the first wireless interface from /proc/net/wireless, e.g.
+// " wlan0: 0000   55.  -55.  -256        0      0      0      0      0        0"
+fn wifi_signal() -> Option<u8> {
+    let wireless = std::fs::read_to_string("/proc/net/wireless").ok()?;
+    let quality = wireless
+        .lines()
+        .skip(2)
+        .find_map(|line| line.split_whitespace().nth(2))?
+        .trim_end_matches('.')
+        .parse::<f32>()
+        .ok()?;
+
+    // Drivers report link quality out of 70
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Some((quality / 70.0 * 100.0).clamp(0.0, 100.0) as u8)
+}
+
+fn host_resolves() -> bool {
+    (RELEASE_HOST, 443)
+        .to_socket_addrs()
+        .is_ok_and(|mut addrs| addrs.next().is_some())
+}
+
+// Any HTTP answer counts, only connection failures mean the host is unreachable
+fn host_answers(tls: TlsPolicy) -> bool {
+    let Ok(client) = reqwest::blocking::Client::builder()
+        .danger_accept_invalid_certs(tls == TlsPolicy::AcceptInvalid)
+        .timeout(Duration::from_secs(5))
+        .build()
+    else {
+        return false;
+    };
+
+    client
+        .head(format!("https://{RELEASE_HOST}"))
+        .send()
+        .map_err(|err| println!("Connectivity check failed: {err}"))
+        .is_ok()
+}
+
+// Check the device can reach the release host, cheapest checks first, with
+// the same certificate checks as the downloads
+pub fn check(tls: TlsPolicy) -> NetworkStatus {
+    let status = if !interface_up() {
+        NetworkStatus::NoInterface
+    } else if !host_resolves() {
+        NetworkStatus::NoDns
+    } else if !host_answers(tls) {
+        NetworkStatus::Unreachable
+    } else {
+        NetworkStatus::Online {
+            signal: wifi_signal(),
+        }
+    };
+    println!("Network status: {status:?}");
+    status
+}
+
+// Keep the Wi-Fi indicator up to date. Only the interface and signal are
+// polled, whether the host is reachable is left to the last full check. When
+// the interface comes back the full check runs again, and `on_reconnect` is
+// called if it succeeds.
+pub fn watch(app_state: &'static AppStateManager, on_reconnect: impl Fn() + Send + 'static) {
+    thread::spawn(move || loop {
+        let previous = app_state.network();
+        let status = match previous {
+            _ if !interface_up() => Some(NetworkStatus::NoInterface),
+            Some(NetworkStatus::NoDns | NetworkStatus::Unreachable) => previous,
+            // Not checked yet
+            None => None,
+            Some(NetworkStatus::NoInterface) => Some(check(app_state.settings().tls)),
+            Some(NetworkStatus::Online { .. }) => Some(NetworkStatus::Online {
+                signal: wifi_signal(),
+            }),
+        };
+        app_state.set_network(status);
+        if previous == Some(NetworkStatus::NoInterface)
+            && status.is_some_and(NetworkStatus::is_online)
+        {
+            on_reconnect();
+        }
+        thread::sleep(Duration::from_secs(10));
+    });
+}
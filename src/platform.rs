@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use crate::version::InstalledVersion;
+
+// Devices NextUI (and this updater) is built for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    Tg5040,
+    Tg5050,
+    My355,
+}
+
+impl Platform {
+    pub const ALL: [Self; 3] = [Self::Tg5040, Self::Tg5050, Self::My355];
+
+    // Name used in folder and asset names
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Tg5040 => "tg5040",
+            Self::Tg5050 => "tg5050",
+            Self::My355 => "my355",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|platform| platform.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    // Platform named somewhere in `text`, e.g. in an asset name
+    fn mentioned_in(text: &str) -> Option<Self> {
+        let text = text.to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|platform| text.contains(platform.name()))
+    }
+
+    // Platform of the running device, from (in order) the PLATFORM variable set
+    // by the NextUI launcher, the Tools/<platform>/ folder the pak runs from,
+    // the installed version.txt and the device tree
+    pub fn detect(installed: Option<&InstalledVersion>) -> Option<Self> {
+        let from_env = std::env::var("PLATFORM")
+            .ok()
+            .and_then(|name| Self::from_name(&name));
+
+        let from_pak_path = || {
+            let exe = std::env::current_exe().ok()?;
+            let mut components = exe.components().map(|c| c.as_os_str().to_string_lossy());
+            components.find(|c| c == "Tools")?;
+            Self::from_name(&components.next()?)
+        };
+
+        let from_version = || Self::from_name(installed?.platform.as_deref()?);
+
+        let from_device_tree = || {
+            let model = std::fs::read_to_string(Path::new("/proc/device-tree/model")).ok()?;
+            Self::mentioned_in(&model)
+        };
+
+        let platform = from_env
+            .or_else(from_pak_path)
+            .or_else(from_version)
+            .or_else(from_device_tree);
+        println!("Detected platform: {platform:?}");
+        platform
+    }
+
+    // Whether an asset is built for another platform
+    pub fn is_foreign_asset(self, asset_name: &str) -> bool {
+        Self::mentioned_in(asset_name).is_some_and(|platform| platform != self)
+    }
+
+    pub fn is_own_asset(self, asset_name: &str) -> bool {
+        Self::mentioned_in(asset_name) == Some(self)
+    }
+
+    // Whether an archive entry belongs to another platform, e.g. Tools/my355/...
+    // when running on a tg5040
+    pub fn is_foreign_path(self, path: &str) -> bool {
+        path.split('/')
+            .filter_map(Self::from_name)
+            .any(|platform| platform != self)
+    }
+}
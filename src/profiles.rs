@@ -0,0 +1,292 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{github::Asset, platform::Platform, SDCARD_ROOT, UPDATER_DATA_DIR};
+
+// Something to do once a profile's files are installed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PostAction {
+    // Run a shell script, relative to the SD card root
+    Script { path: String },
+    // Return to the launcher
+    Quit,
+    Reboot,
+}
+
+fn default_post_actions() -> Vec<PostAction> {
+    vec![PostAction::Reboot]
+}
+
+// What an update installs: which release asset, and which of its files
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Profile {
+    pub name: String,
+    // Shown as the hint of the profile's button
+    #[serde(default)]
+    pub description: String,
+    // Glob patterns for the asset name, tried in order. The first asset is used
+    // if none match.
+    #[serde(default)]
+    pub assets: Vec<String>,
+    // Glob patterns for the files to install, everything if empty. A pattern
+    // matching a folder also matches everything inside it.
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    // Exact paths of the only files to install, e.g. the ones ticked in the
+    // file picker. Unlike `include`, nothing is treated as a pattern.
+    #[serde(default)]
+    pub files: Vec<String>,
+    // Skip Roms folders whose emulator tag, e.g. "(GBA)", already has a folder
+    #[serde(default)]
+    pub keep_existing_roms: bool,
+    #[serde(default = "default_post_actions")]
+    pub post_actions: Vec<PostAction>,
+}
+
+#[derive(Deserialize)]
+struct ProfilesFile {
+    profiles: Vec<Profile>,
+}
+
+fn profiles_path() -> PathBuf {
+    PathBuf::from(SDCARD_ROOT)
+        .join(UPDATER_DATA_DIR)
+        .join("profiles.json")
+}
+
+// Regex for a glob, where `**` matches across folders (`**/` also none) and `*`
+// and `?` within one. Paths on the SD card are matched case-insensitively.
+fn glob_regex(glob: &str) -> Regex {
+    let mut pattern = String::from("(?i)^");
+    let mut chars = glob.trim_matches('/').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    pattern.push_str("(?:.*/)?");
+                } else {
+                    pattern.push_str(".*");
+                }
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    // Matching a folder matches its contents too
+    pattern.push_str("(/.*)?$");
+
+    Regex::new(&pattern).expect("Failed to compile glob regex")
+}
+
+fn glob_regexes(globs: &[String]) -> Vec<Regex> {
+    globs.iter().map(|glob| glob_regex(glob)).collect()
+}
+
+// Whether a Roms folder for the emulator tagged in `file` (e.g. "(GBA)") exists
+fn roms_folder_exists(file: &str, emu_tag_re: &Regex) -> bool {
+    let Some(emu) = emu_tag_re
+        .captures(file)
+        .and_then(|captures| captures.name("emu"))
+        .map(|emu| emu.as_str())
+    else {
+        return false;
+    };
+
+    // Check if the emu tag already exists in the roms folder
+    let exists = std::fs::read_dir(PathBuf::from(SDCARD_ROOT).join("Roms")).is_ok_and(|d| {
+        d.filter_map(std::result::Result::ok).any(|e| {
+            e.file_name()
+                .to_string_lossy()
+                .contains(format!("({emu})").as_str())
+        })
+    });
+    if exists {
+        println!("Roms folder for {emu} already exists, skipping");
+    }
+    exists
+}
+
+impl Profile {
+    // The built-in Quick and Full updates, used when no profiles are configured
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self {
+                name: "Quick Update".to_string(),
+                description: "Update MinUI.zip only".to_string(),
+                assets: vec!["*base*".to_string()],
+                include: vec!["MinUI.zip".to_string(), "trimui".to_string()],
+                exclude: vec![],
+                files: vec![],
+                keep_existing_roms: false,
+                post_actions: default_post_actions(),
+            },
+            Self {
+                name: "Full Update".to_string(),
+                description: "Extract full zip files (base + extras)".to_string(),
+                assets: vec!["*all*".to_string()],
+                include: vec![],
+                exclude: vec![],
+                files: vec![],
+                keep_existing_roms: true,
+                post_actions: default_post_actions(),
+            },
+        ]
+    }
+
+    // Profiles from profiles.json, falling back to the defaults if it is
+    // missing, invalid or empty
+    pub fn load_all() -> Vec<Self> {
+        let Ok(contents) = std::fs::read_to_string(profiles_path()) else {
+            return Self::defaults();
+        };
+
+        match serde_json::from_str::<ProfilesFile>(&contents) {
+            Ok(file) if !file.profiles.is_empty() => file.profiles,
+            Ok(_) => Self::defaults(),
+            Err(err) => {
+                println!("Ignoring invalid profiles file: {err}");
+                Self::defaults()
+            }
+        }
+    }
+
+    // Asset matching the first possible pattern. Assets built for the running
+    // platform are preferred, those built for other platforms are skipped.
+    pub fn find_asset<'a>(
+        &self,
+        assets: &'a [Asset],
+        platform: Option<Platform>,
+    ) -> Option<&'a Asset> {
+        let pick = |candidates: Vec<&'a Asset>| {
+            let Some(platform) = platform else {
+                return candidates.first().copied();
+            };
+            candidates
+                .iter()
+                .find(|a| platform.is_own_asset(&a.name))
+                .or_else(|| {
+                    candidates
+                        .iter()
+                        .find(|a| !platform.is_foreign_asset(&a.name))
+                })
+                .copied()
+        };
+
+        glob_regexes(&self.assets)
+            .iter()
+            .find_map(|re| pick(assets.iter().filter(|a| re.is_match(&a.name)).collect()))
+            .or_else(|| pick(assets.iter().collect()))
+    }
+
+    // Filter deciding which archive entries the profile installs. Other
+    // platforms' folders are skipped.
+    pub fn file_filter(&self, platform: Option<Platform>) -> impl Fn(&str) -> bool {
+        let include = glob_regexes(&self.include);
+        let exclude = glob_regexes(&self.exclude);
+        let files: HashSet<String> = self.files.iter().cloned().collect();
+        let keep_existing_roms = self.keep_existing_roms;
+        let emu_tag_re = Regex::new(r"\((?<emu>\w+)\)").expect("Failed to compile regex");
+
+        move |file| {
+            if platform.is_some_and(|platform| platform.is_foreign_path(file)) {
+                return false;
+            }
+            if !files.is_empty() && !files.contains(file) {
+                return false;
+            }
+            if !include.is_empty() && !include.iter().any(|re| re.is_match(file)) {
+                return false;
+            }
+            if exclude.iter().any(|re| re.is_match(file)) {
+                return false;
+            }
+            if keep_existing_roms && file.starts_with("Roms/") {
+                return !roms_folder_exists(file, &emu_tag_re);
+            }
+
+            true
+        }
+    }
+
+    pub fn reboots(&self) -> bool {
+        self.post_actions.contains(&PostAction::Reboot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(glob: &str, path: &str) -> bool {
+        glob_regex(glob).is_match(path)
+    }
+
+    #[test]
+    fn double_star_slash_matches_any_number_of_folders() {
+        assert!(matches("**/*.txt", "readme.txt"));
+        assert!(matches("**/*.txt", "Bios/readme.txt"));
+        assert!(matches("**/*.txt", "Tools/tg5040/app.pak/readme.txt"));
+        assert!(!matches("**/*.txt", "readme.md"));
+
+        assert!(matches("Tools/**/launch.sh", "Tools/launch.sh"));
+        assert!(matches(
+            "Tools/**/launch.sh",
+            "Tools/tg5040/app.pak/launch.sh"
+        ));
+        assert!(!matches("Tools/**/launch.sh", "Emus/launch.sh"));
+    }
+
+    #[test]
+    fn double_star_matches_across_folders() {
+        assert!(matches("Tools/**", "Tools/tg5040/app.pak/launch.sh"));
+        assert!(matches("**.txt", "Bios/readme.txt"));
+    }
+
+    #[test]
+    fn single_star_and_question_mark_stay_in_one_folder() {
+        assert!(matches("*.txt", "readme.txt"));
+        assert!(!matches("*.txt", "Bios/readme.txt"));
+        assert!(matches("Roms/?BA", "Roms/GBA"));
+        assert!(!matches("Roms/?BA", "Roms/xGBA"));
+        assert!(!matches("Roms?GBA", "Roms/GBA"));
+    }
+
+    #[test]
+    fn folder_matches_its_contents() {
+        assert!(matches("Tools", "Tools"));
+        assert!(matches("Tools/", "Tools/tg5040/app.pak/launch.sh"));
+        assert!(!matches("Tools", "Tools2/launch.sh"));
+    }
+
+    #[test]
+    fn files_match_exactly() {
+        let profile = Profile {
+            files: vec!["Tools/a*b.txt".to_string(), "Tools/app.pak".to_string()],
+            ..Profile::defaults().remove(1)
+        };
+        let filter = profile.file_filter(None);
+        assert!(filter("Tools/a*b.txt"));
+        assert!(!filter("Tools/aXb.txt"));
+        assert!(!filter("Tools/app.pak/launch.sh"));
+        assert!(!filter("tools/a*b.txt"));
+    }
+
+    #[test]
+    fn matches_case_insensitively_and_escapes_regex_characters() {
+        assert!(matches("bios/*.TXT", "Bios/readme.txt"));
+        assert!(matches(
+            "Roms/Game Boy (GB)",
+            "Roms/Game Boy (GB)/tetris.gb"
+        ));
+        assert!(!matches("a.b", "axb"));
+    }
+}
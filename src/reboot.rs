@@ -0,0 +1,97 @@
+use std::process::Command;
+
+use crate::platform::Platform;
+
+// Exit code asking launch.sh to reboot the device
+pub const REBOOT_EXIT_CODE: i32 = 6;
+
+// How the device is rebooted once an update is installed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RebootStrategy {
+    // Run `reboot`
+    System,
+    // Flush pending writes to the SD card, then run `reboot`
+    SyncThenReboot,
+    // Flush pending writes and exit with REBOOT_EXIT_CODE, leaving the reboot to launch.sh
+    Exit,
+    // Don't reboot, e.g. when testing on a desktop
+    Noop,
+}
+
+fn run(command: &str) -> crate::Result<()> {
+    let status = Command::new(command).status()?;
+    if !status.success() {
+        return Err(format!("{command} failed ({status})").into());
+    }
+    Ok(())
+}
+
+// Make sure everything extracted is on the SD card before the power goes
+fn sync() -> crate::Result<()> {
+    run("sync")
+}
+
+impl RebootStrategy {
+    // Strategy named on the command line: `system`, `sync`, `exit` or `none`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "system" => Some(Self::System),
+            "sync" => Some(Self::SyncThenReboot),
+            "exit" => Some(Self::Exit),
+            "none" => Some(Self::Noop),
+            _ => None,
+        }
+    }
+
+    // Reboot devices running NextUI, but not the machine of someone running the
+    // updater on a desktop
+    pub fn default_for(platform: Option<Platform>) -> Self {
+        if platform.is_some() {
+            Self::SyncThenReboot
+        } else {
+            Self::Noop
+        }
+    }
+
+    // Only returns if the reboot is left out (Noop), or once `reboot` has been
+    // started successfully
+    pub fn reboot(self) -> crate::Result<()> {
+        println!("Rebooting: {self:?}");
+        match self {
+            Self::System => run("reboot"),
+            Self::SyncThenReboot => {
+                sync()?;
+                run("reboot")
+            }
+            Self::Exit => {
+                sync()?;
+                std::process::exit(REBOOT_EXIT_CODE);
+            }
+            Self::Noop => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_strategy_names() {
+        assert_eq!(
+            RebootStrategy::from_name("sync"),
+            Some(RebootStrategy::SyncThenReboot)
+        );
+        assert_eq!(
+            RebootStrategy::from_name("exit"),
+            Some(RebootStrategy::Exit)
+        );
+        assert_eq!(
+            RebootStrategy::from_name("none"),
+            Some(RebootStrategy::Noop)
+        );
+        // Only REBOOT_EXIT_CODE makes launch.sh reboot, 5 would restart the updater
+        assert_eq!(RebootStrategy::from_name("exit:5"), None);
+        assert_eq!(RebootStrategy::from_name("halt"), None);
+    }
+}
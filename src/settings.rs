@@ -0,0 +1,498 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{atomic_write::write_atomically, Result, SDCARD_ROOT, UPDATER_DATA_DIR};
+
+// Version of the settings file layout, bumped with each change needing a migration
+pub const SETTINGS_VERSION: u32 = 2;
+
+// How long "Not now" postpones updating the updater
+const SELF_UPDATE_POSTPONE: Duration = Duration::from_hours(3 * 24);
+
+// Repositories NextUI releases can be fetched from, offered in the settings screen
+const KNOWN_SOURCE_REPOS: [&str; 2] = ["adam-ikari/NextUI-CN", "LoveRetro/NextUI"];
+
+// GitHub download proxies offered in the settings screen
+const KNOWN_MIRRORS: [&str; 2] = ["https://ghfast.top/", "https://gh-proxy.com/"];
+
+// What to do with installed files the user has modified since they were installed
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModifiedFilesPolicy {
+    // Leave the user's version in place, skipping the file from the update
+    Keep,
+    // Save the user's version as "<name>.bak", then install the new file
+    #[default]
+    Backup,
+    // Ask during the update
+    Prompt,
+}
+
+// Which NextUI releases are offered
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    #[default]
+    Stable,
+    // Stable releases and pre-releases
+    Prerelease,
+}
+
+// Whether HTTPS certificates are checked
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TlsPolicy {
+    Strict,
+    // Devices without a clock battery often have a wrong date, which makes valid
+    // certificates look expired
+    #[default]
+    AcceptInvalid,
+}
+
+// Language of the text shown, which decides the font
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Language {
+    // Follow the font chosen in NextUI's settings
+    #[default]
+    Auto,
+    English,
+    Chinese,
+}
+
+// User settings, stored as JSON in the updater's data directory
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    // GitHub repository NextUI releases are fetched from
+    pub source_repo: String,
+    pub channel: Channel,
+    // URL prefixes downloads are retried through when GitHub fails, e.g.
+    // "https://mirror.example.com/" fetches "https://mirror.example.com/https://github.com/..."
+    pub mirrors: Vec<String>,
+    // Mirrors from settings.json, still offered once another choice was made
+    pub custom_mirrors: Vec<String>,
+    pub tls: TlsPolicy,
+    // Folders of files removed by updates kept in removed/, oldest are deleted first
+    pub backups_to_keep: usize,
+    pub modified_files: ModifiedFilesPolicy,
+    // Updates don't start below this battery level, unless charging
+    pub min_battery_percent: u8,
+    // Look for new versions of the updater on start, off for pinned devices
+    pub self_update: bool,
+    pub language: Language,
+    // Updater version the user chose to skip
+    pub skipped_updater_version: Option<String>,
+    // When the user last postponed updating the updater, in seconds since the epoch
+    pub self_update_postponed_at: Option<u64>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            source_repo: KNOWN_SOURCE_REPOS[0].to_string(),
+            channel: Channel::default(),
+            mirrors: vec![],
+            custom_mirrors: vec![],
+            tls: TlsPolicy::default(),
+            backups_to_keep: 3,
+            modified_files: ModifiedFilesPolicy::default(),
+            min_battery_percent: 30,
+            self_update: true,
+            language: Language::default(),
+            skipped_updater_version: None,
+            self_update_postponed_at: None,
+        }
+    }
+}
+
+// Options editable in the settings screen, in the order shown
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingsField {
+    SourceRepo,
+    Channel,
+    Mirrors,
+    Tls,
+    BackupsToKeep,
+    ModifiedFiles,
+    MinBattery,
+    SelfUpdate,
+    Language,
+}
+
+impl SettingsField {
+    pub const ALL: [Self; 9] = [
+        Self::SourceRepo,
+        Self::Channel,
+        Self::Mirrors,
+        Self::Tls,
+        Self::BackupsToKeep,
+        Self::ModifiedFiles,
+        Self::MinBattery,
+        Self::SelfUpdate,
+        Self::Language,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::SourceRepo => "Source",
+            Self::Channel => "Channel",
+            Self::Mirrors => "Mirrors",
+            Self::Tls => "Certificates",
+            Self::BackupsToKeep => "Backups to keep",
+            Self::ModifiedFiles => "Modified files",
+            Self::MinBattery => "Minimum battery",
+            Self::SelfUpdate => "Update the updater",
+            Self::Language => "Language",
+        }
+    }
+
+    // Shown as the hint of the option
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::SourceRepo => "GitHub repository NextUI is downloaded from",
+            Self::Channel => "Also offer pre-releases",
+            Self::Mirrors => "Proxies retried when GitHub downloads fail, more in settings.json",
+            Self::Tls => "Check certificates, needs a correct date on the device",
+            Self::BackupsToKeep => {
+                "Copies of removed files kept in .userdata/shared/updater/removed"
+            }
+            Self::ModifiedFiles => "What to do with files you changed since they were installed",
+            Self::MinBattery => "Updates don't start below this level unless charging",
+            Self::SelfUpdate => "Look for new versions of the updater on start",
+            Self::Language => "Applies after restarting the updater",
+        }
+    }
+}
+
+// Index after (or before, with a negative `direction`) `index` among `len`, wrapping around
+fn cycle_index(len: usize, index: usize, direction: i32) -> usize {
+    if direction < 0 {
+        (index + len - 1) % len
+    } else {
+        (index + 1) % len
+    }
+}
+
+fn cycle<T: Copy + PartialEq>(options: &[T], current: T, direction: i32) -> T {
+    let index = options.iter().position(|&o| o == current).unwrap_or(0);
+    options[cycle_index(options.len(), index, direction)]
+}
+
+fn settings_path() -> PathBuf {
+    PathBuf::from(SDCARD_ROOT)
+        .join(UPDATER_DATA_DIR)
+        .join("settings.json")
+}
+
+// Bring settings written by an older updater up to SETTINGS_VERSION
+fn migrate(settings: &mut Value) {
+    let Some(object) = settings.as_object_mut() else {
+        return;
+    };
+    // Version 1 files predate the version field
+    let version = object.get("version").and_then(Value::as_u64).unwrap_or(1);
+
+    if version < 2 {
+        // Options added in version 2 take their defaults. Version 1 took battery
+        // levels above 100%.
+        if let Some(level) = object.get("min_battery_percent").and_then(Value::as_u64) {
+            object.insert("min_battery_percent".to_string(), level.min(100).into());
+        }
+    }
+
+    object.insert("version".to_string(), SETTINGS_VERSION.into());
+}
+
+impl Settings {
+    // Missing or unreadable settings fall back to the defaults. Settings of an
+    // older updater are migrated and saved in the current layout.
+    pub fn load() -> Self {
+        let Ok(contents) = std::fs::read_to_string(settings_path()) else {
+            return Self::default();
+        };
+
+        let mut value: Value = match serde_json::from_str(&contents) {
+            Ok(value) => value,
+            Err(err) => {
+                println!("Ignoring invalid settings file: {err}");
+                return Self::default();
+            }
+        };
+        let version = value.get("version").and_then(Value::as_u64).unwrap_or(1);
+        if version > u64::from(SETTINGS_VERSION) {
+            println!("Settings file version {version} is newer than this updater's");
+        } else if version < u64::from(SETTINGS_VERSION) {
+            println!("Migrating settings from version {version}");
+            migrate(&mut value);
+        }
+
+        let settings = serde_json::from_value::<Self>(value).unwrap_or_else(|err| {
+            println!("Ignoring invalid settings file: {err}");
+            Self::default()
+        });
+        if version < u64::from(SETTINGS_VERSION) {
+            if let Err(err) = settings.save() {
+                println!("Failed to save migrated settings: {err}");
+            }
+        }
+        settings
+    }
+
+    // Value of `field` as shown in the settings screen
+    pub fn value(&self, field: SettingsField) -> String {
+        let on_off = |on: bool| if on { "On" } else { "Off" }.to_string();
+        match field {
+            SettingsField::SourceRepo => self.source_repo.clone(),
+            SettingsField::Channel => match self.channel {
+                Channel::Stable => "Stable".to_string(),
+                Channel::Prerelease => "Pre-release".to_string(),
+            },
+            SettingsField::Mirrors => match self.mirrors.as_slice() {
+                [] => "None".to_string(),
+                [mirror] => mirror
+                    .trim_start_matches("https://")
+                    .trim_start_matches("http://")
+                    .trim_end_matches('/')
+                    .to_string(),
+                mirrors => format!("{} mirrors", mirrors.len()),
+            },
+            SettingsField::Tls => match self.tls {
+                TlsPolicy::Strict => "Strict".to_string(),
+                TlsPolicy::AcceptInvalid => "Accept invalid".to_string(),
+            },
+            SettingsField::BackupsToKeep => self.backups_to_keep.to_string(),
+            SettingsField::ModifiedFiles => match self.modified_files {
+                ModifiedFilesPolicy::Keep => "Keep mine".to_string(),
+                ModifiedFilesPolicy::Backup => "Back up".to_string(),
+                ModifiedFilesPolicy::Prompt => "Ask".to_string(),
+            },
+            SettingsField::MinBattery => format!("{}%", self.min_battery_percent),
+            SettingsField::SelfUpdate => on_off(self.self_update),
+            SettingsField::Language => match self.language {
+                Language::Auto => "Auto".to_string(),
+                Language::English => "English".to_string(),
+                Language::Chinese => "Chinese".to_string(),
+            },
+        }
+    }
+
+    // Change `field` to its next (or previous, with a negative `direction`) value
+    pub fn adjust(&mut self, field: SettingsField, direction: i32) {
+        match field {
+            SettingsField::SourceRepo => {
+                // Keep a custom repository from settings.json among the choices
+                let mut repos: Vec<String> =
+                    KNOWN_SOURCE_REPOS.iter().map(ToString::to_string).collect();
+                if !repos.contains(&self.source_repo) {
+                    repos.push(self.source_repo.clone());
+                }
+                let index = repos.iter().position(|r| *r == self.source_repo);
+                let next = cycle_index(repos.len(), index.unwrap_or(0), direction);
+                self.source_repo.clone_from(&repos[next]);
+            }
+            SettingsField::Channel => {
+                self.channel = cycle(
+                    &[Channel::Stable, Channel::Prerelease],
+                    self.channel,
+                    direction,
+                );
+            }
+            SettingsField::Mirrors => {
+                // No mirrors, each known one, all of them, and a custom list
+                // from settings.json
+                let mut choices: Vec<Vec<String>> = vec![vec![]];
+                choices.extend(KNOWN_MIRRORS.iter().map(|m| vec![m.to_string()]));
+                choices.push(KNOWN_MIRRORS.iter().map(ToString::to_string).collect());
+                if !choices.contains(&self.mirrors) {
+                    self.custom_mirrors.clone_from(&self.mirrors);
+                }
+                if !self.custom_mirrors.is_empty() && !choices.contains(&self.custom_mirrors) {
+                    choices.push(self.custom_mirrors.clone());
+                }
+                let index = choices.iter().position(|m| *m == self.mirrors);
+                let next = cycle_index(choices.len(), index.unwrap_or(0), direction);
+                self.mirrors.clone_from(&choices[next]);
+            }
+            SettingsField::Tls => {
+                self.tls = cycle(
+                    &[TlsPolicy::Strict, TlsPolicy::AcceptInvalid],
+                    self.tls,
+                    direction,
+                );
+            }
+            SettingsField::BackupsToKeep => {
+                self.backups_to_keep = if direction < 0 {
+                    self.backups_to_keep.saturating_sub(1)
+                } else {
+                    (self.backups_to_keep + 1).min(10)
+                };
+            }
+            SettingsField::ModifiedFiles => {
+                self.modified_files = cycle(
+                    &[
+                        ModifiedFilesPolicy::Keep,
+                        ModifiedFilesPolicy::Backup,
+                        ModifiedFilesPolicy::Prompt,
+                    ],
+                    self.modified_files,
+                    direction,
+                );
+            }
+            SettingsField::MinBattery => {
+                self.min_battery_percent = if direction < 0 {
+                    self.min_battery_percent.saturating_sub(5)
+                } else {
+                    (self.min_battery_percent + 5).min(100)
+                };
+            }
+            SettingsField::SelfUpdate => self.self_update = !self.self_update,
+            SettingsField::Language => {
+                self.language = cycle(
+                    &[Language::Auto, Language::English, Language::Chinese],
+                    self.language,
+                    direction,
+                );
+            }
+        }
+    }
+
+    pub fn postpone_self_update(&mut self) {
+        self.self_update_postponed_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .ok()
+            .map(|now| now.as_secs());
+    }
+
+    // Whether updating the updater was postponed recently. Devices without a
+    // clock battery may go back in time, which ends the postponement.
+    pub fn self_update_postponed(&self) -> bool {
+        let Some(postponed_at) = self.self_update_postponed_at else {
+            return false;
+        };
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH + Duration::from_secs(postponed_at))
+            .is_ok_and(|elapsed| elapsed < SELF_UPDATE_POSTPONE)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        write_atomically(
+            &settings_path(),
+            serde_json::to_string_pretty(self)?.as_bytes(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn migrated(mut value: Value) -> Settings {
+        migrate(&mut value);
+        assert_eq!(value["version"], json!(SETTINGS_VERSION));
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn migrates_version_1_settings() {
+        // Version 1 files have no version field, nor the options added since
+        let settings = migrated(json!({
+            "modified_files": "keep",
+            "min_battery_percent": 150,
+            "self_update": false,
+            "skipped_updater_version": "0.8.0",
+        }));
+
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.modified_files, ModifiedFilesPolicy::Keep);
+        assert_eq!(settings.min_battery_percent, 100);
+        assert!(!settings.self_update);
+        assert_eq!(settings.skipped_updater_version.as_deref(), Some("0.8.0"));
+
+        let defaults = Settings::default();
+        assert_eq!(settings.source_repo, defaults.source_repo);
+        assert_eq!(settings.channel, Channel::Stable);
+        assert!(settings.mirrors.is_empty());
+        assert_eq!(settings.tls, TlsPolicy::AcceptInvalid);
+        assert_eq!(settings.backups_to_keep, defaults.backups_to_keep);
+        assert_eq!(settings.language, Language::Auto);
+    }
+
+    #[test]
+    fn keeps_valid_version_1_values() {
+        let settings = migrated(json!({ "version": 1, "min_battery_percent": 20 }));
+        assert_eq!(settings.min_battery_percent, 20);
+    }
+
+    #[test]
+    fn leaves_current_settings_alone() {
+        let current = json!({
+            "version": SETTINGS_VERSION,
+            "source_repo": "LoveRetro/NextUI",
+            "channel": "prerelease",
+            "mirrors": ["https://mirror.example.com/"],
+            "tls": "strict",
+            "language": "chinese",
+        });
+        let mut value = current.clone();
+        migrate(&mut value);
+        assert_eq!(value, current);
+
+        let settings: Settings = serde_json::from_value(value).unwrap();
+        assert_eq!(settings.source_repo, "LoveRetro/NextUI");
+        assert_eq!(settings.channel, Channel::Prerelease);
+        assert_eq!(settings.tls, TlsPolicy::Strict);
+        assert_eq!(settings.language, Language::Chinese);
+    }
+
+    #[test]
+    fn ignores_settings_which_are_not_an_object() {
+        let mut value = json!([1, 2]);
+        migrate(&mut value);
+        assert_eq!(value, json!([1, 2]));
+    }
+
+    #[test]
+    fn cycles_mirrors() {
+        let mut settings = Settings::default();
+        let mut seen = vec![];
+        for _ in 0..4 {
+            settings.adjust(SettingsField::Mirrors, 1);
+            seen.push(settings.mirrors.clone());
+        }
+        assert_eq!(seen[0], vec![KNOWN_MIRRORS[0].to_string()]);
+        assert_eq!(seen[1], vec![KNOWN_MIRRORS[1].to_string()]);
+        assert_eq!(seen[2].len(), KNOWN_MIRRORS.len());
+        assert!(seen[3].is_empty());
+
+        settings.adjust(SettingsField::Mirrors, -1);
+        assert_eq!(settings.mirrors.len(), KNOWN_MIRRORS.len());
+    }
+
+    #[test]
+    fn offers_custom_mirrors_among_the_choices() {
+        let custom = vec!["https://mirror.example.com/".to_string()];
+        let mut settings = Settings {
+            mirrors: custom.clone(),
+            ..Settings::default()
+        };
+
+        settings.adjust(SettingsField::Mirrors, -1);
+        assert_eq!(settings.mirrors.len(), KNOWN_MIRRORS.len());
+        settings.adjust(SettingsField::Mirrors, 1);
+        assert_eq!(settings.mirrors, custom);
+
+        // Still offered after going round through no mirrors
+        settings.adjust(SettingsField::Mirrors, 1);
+        assert!(settings.mirrors.is_empty());
+        settings.adjust(SettingsField::Mirrors, -1);
+        assert_eq!(settings.mirrors, custom);
+    }
+}
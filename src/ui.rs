@@ -1,5 +1,13 @@
-use crate::app_state::{AppStateManager, Progress, Submenu};
-use crate::update::do_update;
+use crate::app_state::{format_bytes, AppStateManager, Progress, Submenu};
+use crate::file_picker::Selection;
+use crate::network::NetworkStatus;
+use crate::settings::{Language, ModifiedFilesPolicy, SettingsField};
+use crate::update::{
+    cancel_staged_update, change_setting, confirm_self_update, do_apply_staged_update, do_update,
+    install_from_sd_card, install_selected_files, load_more_nextui_releases, open_file_picker,
+    resolve_selected_nextui_release, retry_connection, stage_update, StagedUpdate,
+};
+use crate::version::is_older_than_installed;
 use egui::{Button, Color32, FullOutput, ProgressBar};
 use egui_backend::egui;
 use egui_backend::{sdl2::event::Event, DpiScaling, ShaderVersion};
@@ -11,7 +19,7 @@ use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::{io::Read, sync::Arc, time::Instant};
 
-use crate::{Result, SDCARD_ROOT};
+use crate::{Result, SDCARD_ROOT, UPDATER_DATA_DIR};
 
 const DEFAULT_WIDTH: u32 = 1024;
 const DEFAULT_HEIGHT: u32 = 768;
@@ -35,7 +43,14 @@ fn text(s: impl Into<String>) -> RichText {
 
 #[allow(clippy::too_many_lines)]
 fn nextui_ui(ui: &mut egui::Ui, app_state: &'static AppStateManager) -> egui::Response {
+    if let Some(staged) = app_state.staged_update() {
+        if !app_state.release_selection_menu() {
+            return staged_update_ui(ui, app_state, &staged);
+        }
+    }
+
     let current_version = app_state.current_version();
+    let installed_version_known = current_version.as_ref().is_some_and(|v| v.commit.is_some());
     let mut latest_release = app_state.nextui_release().clone();
     let mut latest_tag = app_state.nextui_tag().clone();
     let mut update_available = true;
@@ -46,11 +61,17 @@ fn nextui_ui(ui: &mut egui::Ui, app_state: &'static AppStateManager) -> egui::Re
         let relase_and_tag_vector = app_state.nextui_releases_and_tags().unwrap_or_default();
         let release_and_tag = relase_and_tag_vector.get(index).cloned();
         latest_release = release_and_tag.as_ref().map(|r| r.release.clone());
-        latest_tag = release_and_tag.map(|r| r.tag.clone());
+        latest_tag = release_and_tag.and_then(|r| r.tag);
     }
 
-    if app_state.release_selection_menu() & !app_state.release_selection_confirmed() {
-        ui.add_space(scale(16.0));
+    let show_downgrade_warning = downgrade_warning_pending(app_state);
+
+    if show_downgrade_warning {
+        if let Some(release) = &latest_release {
+            let selected_tag = hint_wrap_nextui_tag(app_state, &release.tag_name);
+            ui.label(text(format!("Selected Version: {selected_tag}")));
+        }
+        ui.add_space(scale(8.0));
         ui.label(text(
             "WARNING\n\
             Downgrades are not fully supported by NextUI!\n\
@@ -62,7 +83,7 @@ fn nextui_ui(ui: &mut egui::Ui, app_state: &'static AppStateManager) -> egui::Re
         match (current_version, latest_tag, latest_release) {
             (Some(current_version), Some(tag), _) => {
                 let selected_tag = hint_wrap_nextui_tag(app_state, &tag.name);
-                if tag.commit.sha.starts_with(&current_version) && !latest_discarded {
+                if current_version.is_commit(&tag.commit.sha) && !latest_discarded {
                     if app_state.release_selection_menu() {
                         // selection view
                         ui.label(
@@ -94,14 +115,25 @@ fn nextui_ui(ui: &mut egui::Ui, app_state: &'static AppStateManager) -> egui::Re
                 ui.label(text("No release information available"));
             }
         }
+
+        if !installed_version_known {
+            ui.label(
+                text("Installed version could not be determined")
+                    .color(Color32::from_rgb(150, 150, 150)),
+            );
+        }
+
+        if app_state.release_selection_menu() && app_state.loading_more_releases() {
+            ui.label(text("Loading older releases...").color(Color32::from_rgb(150, 150, 150)));
+        }
     }
 
     ui.add_space(scale(8.0));
 
-    if app_state.release_selection_menu() & !app_state.release_selection_confirmed() {
+    if show_downgrade_warning {
         let back_button = ui.button(text("Return"));
         if back_button.clicked() {
-            app_state.set_release_selection_menu(false);
+            app_state.leave_release_selection_menu();
         }
 
         let confirm_button = ui.button(text("Accept Warning"));
@@ -112,42 +144,68 @@ fn nextui_ui(ui: &mut egui::Ui, app_state: &'static AppStateManager) -> egui::Re
         if back_button.has_focus() {
             app_state.set_hint(Some("Return to Latest Version options".to_string()));
         } else if confirm_button.has_focus() {
-            app_state.set_hint(Some("Confirm warning and open update options".to_string()));
+            app_state.set_hint(Some(
+                "Confirm downgrade and open update options".to_string(),
+            ));
         } else {
             app_state.set_hint(None);
         }
 
         back_button
     } else if update_available {
-        let quick_update_button = ui.add(Button::new(text("Quick Update")));
+        let mut first_button = None;
+        let mut focused_profile = None;
+        for (index, profile) in app_state.profiles().into_iter().enumerate() {
+            if index > 0 {
+                ui.add_space(scale(4.0));
+            }
 
-        // Initiate update if button clicked
-        if quick_update_button.clicked() {
-            // Clear any previous errors
-            app_state.set_error(None);
-            do_update(app_state, false);
+            let profile_button = ui.add(Button::new(text(&profile.name)));
+
+            if profile_button.has_focus() {
+                focused_profile = Some(profile.description.clone());
+            }
+
+            // Initiate update if button clicked
+            if profile_button.clicked() {
+                // Clear any previous errors
+                app_state.set_error(None);
+                do_update(app_state, profile, false);
+            }
+
+            first_button.get_or_insert(profile_button);
         }
 
         ui.add_space(scale(4.0));
 
-        let full_update_button = ui.add(Button::new(text("Full Update")));
+        let pick_files_button = ui.add(Button::new(text("Choose Files...")));
+        if pick_files_button.clicked() {
+            open_file_picker(app_state);
+        }
+        if pick_files_button.has_focus() {
+            focused_profile = Some("Install only some files of this version".to_string());
+        }
 
-        if full_update_button.clicked() {
-            // Clear any previous errors
+        if let Some(offline_button) = offline_options_button(ui, app_state) {
+            if offline_button.has_focus() {
+                focused_profile = Some(OFFLINE_OPTIONS_HINT.to_string());
+            }
+        }
+
+        let later_button = ui.add(Button::new(text("Install on Next Boot...")));
+        if later_button.has_focus() {
+            focused_profile =
+                Some("Download an update now, install it when NextUI next starts".to_string());
+        }
+        if later_button.clicked() {
             app_state.set_error(None);
-            do_update(app_state, true);
+            stage_update(app_state);
         }
 
         // HINTS
-        if quick_update_button.has_focus() {
-            app_state.set_hint(Some("Update MinUI.zip only".to_string()));
-        } else if full_update_button.has_focus() {
-            app_state.set_hint(Some("Extract full zip files (base + extras)".to_string()));
-        } else {
-            app_state.set_hint(None);
-        }
+        app_state.set_hint(focused_profile.filter(|description| !description.is_empty()));
 
-        quick_update_button
+        first_button.expect("There is always at least one profile")
     } else {
         let force_button = ui.button(text("Update anyway"));
         if force_button.clicked() {
@@ -157,13 +215,17 @@ fn nextui_ui(ui: &mut egui::Ui, app_state: &'static AppStateManager) -> egui::Re
         let quit_button = ui.button(text("Quit"));
         if quit_button.clicked() {
             if app_state.release_selection_menu() {
-                app_state.set_release_selection_menu(false);
+                app_state.leave_release_selection_menu();
             } else {
                 app_state.set_should_quit(true);
             }
         }
 
-        if quit_button.has_focus() {
+        let offline_button = offline_options_button(ui, app_state);
+
+        if offline_button.is_some_and(|button| button.has_focus()) {
+            app_state.set_hint(Some(OFFLINE_OPTIONS_HINT.to_string()));
+        } else if quit_button.has_focus() {
             if app_state.release_selection_menu() {
                 app_state.set_hint(Some("Return to Latest Version options".to_string()));
             } else {
@@ -179,6 +241,383 @@ fn nextui_ui(ui: &mut egui::Ui, app_state: &'static AppStateManager) -> egui::Re
     }
 }
 
+const OFFLINE_OPTIONS_HINT: &str = "Retry the connection or install from the SD card";
+
+// Leads from the cached releases to the offline screen, only shown while offline
+fn offline_options_button(
+    ui: &mut egui::Ui,
+    app_state: &'static AppStateManager,
+) -> Option<egui::Response> {
+    if app_state.network().is_none_or(NetworkStatus::is_online) {
+        return None;
+    }
+
+    ui.add_space(scale(4.0));
+    let button = ui.button(text("Offline Options..."));
+    if button.clicked() {
+        app_state.show_offline(app_state.sd_card_archive());
+    }
+    Some(button)
+}
+
+fn staged_update_ui(
+    ui: &mut egui::Ui,
+    app_state: &'static AppStateManager,
+    staged: &StagedUpdate,
+) -> egui::Response {
+    ui.label(text(format!(
+        "NextUI {} will be installed on the next boot",
+        staged.version
+    )));
+    ui.label(text(&staged.profile.name).color(Color32::from_rgb(150, 150, 150)));
+    ui.add_space(scale(8.0));
+
+    let install_button = ui.button(text("Install Now"));
+    if install_button.clicked() {
+        do_apply_staged_update(app_state);
+    }
+
+    let cancel_button = ui.button(text("Cancel Pending Update"));
+    if cancel_button.clicked() {
+        cancel_staged_update(app_state);
+    }
+
+    let quit_button = ui.button(text("Quit"));
+    if quit_button.clicked() {
+        app_state.set_should_quit(true);
+    }
+
+    // HINTS
+    if install_button.has_focus() {
+        app_state.set_hint(Some(format!("Install {} now", staged.asset)));
+    } else if cancel_button.has_focus() {
+        app_state.set_hint(Some("Delete the downloaded update".to_string()));
+    } else if quit_button.has_focus() {
+        app_state.set_hint(Some("Quit NextUI Updater".to_string()));
+    } else {
+        app_state.set_hint(None);
+    }
+
+    install_button
+}
+
+fn file_picker_ui(ui: &mut egui::Ui, app_state: &'static AppStateManager) -> egui::Response {
+    let picker = app_state.file_picker().unwrap_or_default();
+    let selected = picker.selected();
+
+    ui.label(text(format!(
+        "{}: {} files selected ({})",
+        picker.asset,
+        selected.len(),
+        format_bytes(picker.selected_size())
+    )));
+    ui.add_space(scale(4.0));
+
+    let mut first_row = None;
+    let mut focused = None;
+    // Leave room for the buttons below the tree
+    let tree_height = (ui.available_height() - scale(70.0)).max(scale(40.0));
+    egui::ScrollArea::vertical()
+        .max_height(tree_height)
+        .show(ui, |ui| {
+            ui.with_layout(egui::Layout::top_down(egui::Align::LEFT), |ui| {
+                for row in picker.rows() {
+                    let checkbox = match row.selection {
+                        // Still focusable, to expand folders and explain why
+                        _ if row.protected => "[#]",
+                        Selection::All => "[x]",
+                        Selection::Partial => "[-]",
+                        Selection::None => "[ ]",
+                    };
+                    let folder_marker = match (row.is_folder, row.expanded) {
+                        (true, true) => "v ",
+                        (true, false) => "> ",
+                        (false, _) => "",
+                    };
+                    let label = format!(
+                        "{}{checkbox} {folder_marker}{}",
+                        "    ".repeat(row.depth),
+                        row.name
+                    );
+
+                    let mut label = text(label);
+                    if row.protected {
+                        label = label.color(Color32::from_rgb(120, 120, 120));
+                    }
+                    let row_button = ui.add(Button::new(label).frame(false));
+                    if row_button.clicked() {
+                        app_state.update_file_picker(|picker| picker.toggle(&row.path));
+                    }
+                    if row_button.gained_focus() {
+                        row_button.scroll_to_me(None);
+                    }
+                    if row_button.has_focus() {
+                        focused = Some(row);
+                    }
+
+                    first_row.get_or_insert(row_button);
+                }
+            });
+        });
+
+    ui.add_space(scale(8.0));
+
+    let install_button = ui.add_enabled(
+        !selected.is_empty(),
+        Button::new(text("Install Selected Files")),
+    );
+    if install_button.clicked() {
+        install_selected_files(app_state);
+    }
+
+    let back_button = ui.button(text("Back"));
+    if back_button.clicked() {
+        app_state.close_file_picker();
+    }
+
+    if let Some(row) = &focused {
+        app_state.set_hint(Some(if row.protected {
+            "Protected location (saves, BIOS, user data), this release may not write here"
+                .to_string()
+        } else if row.is_folder {
+            "A: Tick folder   Left/Right: Collapse/Expand".to_string()
+        } else {
+            "A: Tick file".to_string()
+        }));
+    } else if install_button.has_focus() {
+        app_state.set_hint(Some(
+            "Install the ticked files, without rebooting".to_string(),
+        ));
+    } else if back_button.has_focus() {
+        app_state.set_hint(Some("Return to update options".to_string()));
+    } else {
+        app_state.set_hint(None);
+    }
+    let focused_path = focused.map(|row| row.path);
+    app_state.update_file_picker(|picker| picker.focused = focused_path);
+
+    first_row.unwrap_or(back_button)
+}
+
+fn confirmation_ui(ui: &mut egui::Ui, app_state: &'static AppStateManager) -> egui::Response {
+    let confirmation = app_state.confirmation().unwrap_or_default();
+
+    ui.label(text(&confirmation.message));
+    ui.add_space(scale(8.0));
+
+    let mut first_button = None;
+    for (index, choice) in confirmation.choices.iter().enumerate() {
+        let button = ui.button(text(choice));
+        if button.clicked() {
+            app_state.answer_confirmation(index);
+        }
+        first_button.get_or_insert(button);
+    }
+
+    app_state.set_hint(None);
+
+    // Focus the safe choice first
+    first_button.unwrap_or_else(|| ui.label(""))
+}
+
+fn offline_ui(ui: &mut egui::Ui, app_state: &'static AppStateManager) -> egui::Response {
+    let status = app_state.network().unwrap_or(NetworkStatus::NoInterface);
+    let archive = app_state.sd_card_archive();
+
+    ui.label(text("You are offline"));
+    ui.label(text(status.description()).color(Color32::from_rgb(150, 150, 150)));
+    ui.add_space(scale(8.0));
+
+    let retry_button = ui.button(text("Retry"));
+    if retry_button.clicked() {
+        retry_connection(app_state);
+    }
+
+    let install_button =
+        ui.add_enabled(archive.is_some(), Button::new(text("Install from SD card")));
+    if install_button.clicked() {
+        install_from_sd_card(app_state);
+    }
+
+    // Cached releases can still be browsed and staged from
+    let cached = app_state.nextui_releases_and_tags().is_some();
+    let cached_button = cached.then(|| ui.button(text("Back to Cached Releases")));
+    if cached_button.as_ref().is_some_and(egui::Response::clicked) {
+        app_state.leave_offline();
+    }
+
+    let quit_button = ui.button(text("Quit"));
+    if quit_button.clicked() {
+        app_state.set_should_quit(true);
+    }
+
+    if archive.is_none() {
+        ui.add_space(scale(4.0));
+        ui.label(
+            text("Copy a NextUI zip to the root of the SD card to install it offline")
+                .color(Color32::from_rgb(150, 150, 150)),
+        );
+    }
+
+    // HINTS
+    if retry_button.has_focus() {
+        app_state.set_hint(Some("Check the connection again".to_string()));
+    } else if install_button.has_focus() {
+        app_state.set_hint(
+            archive
+                .and_then(|archive| {
+                    archive
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                })
+                .map(|name| format!("Install {name}")),
+        );
+    } else if cached_button.is_some_and(|button| button.has_focus()) {
+        app_state.set_hint(Some("Show the releases saved the last time".to_string()));
+    } else if quit_button.has_focus() {
+        app_state.set_hint(Some("Quit NextUI Updater".to_string()));
+    } else {
+        app_state.set_hint(None);
+    }
+
+    retry_button
+}
+
+fn settings_ui(ui: &mut egui::Ui, app_state: &'static AppStateManager) -> egui::Response {
+    let settings = app_state.settings();
+
+    let mut first_button = None;
+    let mut focused = None;
+    // Leave room for the back button
+    let list_height = (ui.available_height() - scale(50.0)).max(scale(40.0));
+    egui::ScrollArea::vertical()
+        .max_height(list_height)
+        .show(ui, |ui| {
+            for field in SettingsField::ALL {
+                let label = format!("{}: < {} >", field.label(), settings.value(field));
+                let button = ui.add(Button::new(text(label)).frame(false));
+                if button.clicked() {
+                    change_setting(app_state, field, 1);
+                }
+                if button.gained_focus() {
+                    button.scroll_to_me(None);
+                }
+                if button.has_focus() {
+                    focused = Some(field);
+                }
+                first_button.get_or_insert(button);
+            }
+        });
+
+    ui.add_space(scale(8.0));
+
+    let back_button = ui.button(text("Back"));
+    if back_button.clicked() {
+        app_state.close_settings();
+    }
+
+    // HINTS
+    app_state.set_settings_focus(focused);
+    if let Some(field) = focused {
+        app_state.set_hint(Some(field.description().to_string()));
+    } else if back_button.has_focus() {
+        app_state.set_hint(Some("Return to the updater".to_string()));
+    } else {
+        app_state.set_hint(None);
+    }
+
+    first_button.unwrap_or(back_button)
+}
+
+// List the first few `paths`, as the screen only fits so many
+fn path_list(ui: &mut egui::Ui, paths: &[String]) {
+    const LISTED_PATHS: usize = 3;
+
+    for path in paths.iter().take(LISTED_PATHS) {
+        ui.label(text(path).color(Color32::from_rgb(150, 150, 150)));
+    }
+    if paths.len() > LISTED_PATHS {
+        ui.label(
+            text(format!("...and {} more", paths.len() - LISTED_PATHS))
+                .color(Color32::from_rgb(150, 150, 150)),
+        );
+    }
+}
+
+fn install_plan_ui(ui: &mut egui::Ui, app_state: &'static AppStateManager) -> egui::Response {
+    let plan = app_state.install_plan().unwrap_or_default();
+
+    ui.label(text(format!("{} files will be installed", plan.install)));
+
+    if !plan.modified.is_empty() {
+        ui.add_space(scale(4.0));
+        ui.label(text(match plan.modified_policy {
+            Some(ModifiedFilesPolicy::Keep) => {
+                format!("{} files you modified will be kept:", plan.modified.len())
+            }
+            Some(_) => format!(
+                "{} files you modified will be saved as .bak:",
+                plan.modified.len()
+            ),
+            None => format!(
+                "{} files were modified since they were installed:",
+                plan.modified.len()
+            ),
+        }));
+        path_list(ui, &plan.modified);
+    }
+
+    if !plan.remove.is_empty() {
+        ui.add_space(scale(4.0));
+        ui.label(text(format!(
+            "{} files are no longer part of NextUI and will be removed:",
+            plan.remove.len()
+        )));
+        path_list(ui, &plan.remove);
+    }
+
+    ui.add_space(scale(8.0));
+
+    if let Some(modified_policy) = plan.modified_policy {
+        let install_button = ui.button(text("Install"));
+        if install_button.clicked() {
+            app_state.answer_install_plan(modified_policy);
+        }
+
+        if install_button.has_focus() {
+            app_state.set_hint(Some(format!(
+                "Removed files are backed up to {UPDATER_DATA_DIR}removed/"
+            )));
+        } else {
+            app_state.set_hint(None);
+        }
+
+        return install_button;
+    }
+
+    let keep_button = ui.button(text("Keep my versions"));
+    if keep_button.clicked() {
+        app_state.answer_install_plan(ModifiedFilesPolicy::Keep);
+    }
+
+    let backup_button = ui.button(text("Back up and replace"));
+    if backup_button.clicked() {
+        app_state.answer_install_plan(ModifiedFilesPolicy::Backup);
+    }
+
+    if keep_button.has_focus() {
+        app_state.set_hint(Some("Skip modified files in this update".to_string()));
+    } else if backup_button.has_focus() {
+        app_state.set_hint(Some(
+            "Save modified files as .bak, then install the new ones".to_string(),
+        ));
+    } else {
+        app_state.set_hint(None);
+    }
+
+    keep_button
+}
+
 // Map controller buttons to keyboard keys
 fn controller_to_key(button: sdl2::controller::Button) -> Option<sdl2::keyboard::Keycode> {
     match button {
@@ -351,8 +790,8 @@ fn init_sdl(
     Ok((sdl_context, window, event_pump, controller))
 }
 
-// Load font from file
-fn load_font() -> Result<FontDefinitions> {
+// Load font from file, for `language` or else the font picked in NextUI's settings
+fn load_font(language: Language) -> Result<FontDefinitions> {
     fn get_font_preference() -> Result<usize> {
         // Load NextUI settings
         let mut settings_file =
@@ -361,16 +800,26 @@ fn load_font() -> Result<FontDefinitions> {
         let mut settings = String::new();
         settings_file.read_to_string(&mut settings)?;
 
-        // Very crappy parser
-        Ok(settings.contains("font=1").into())
+        // One "key=value" per line
+        let font = settings
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .find(|(key, _)| key.trim() == "font")
+            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        Ok(font.min(FONTS.len() - 1))
     }
 
+    let font = match language {
+        Language::Auto => get_font_preference().unwrap_or(0),
+        Language::English => 0,
+        // The rounded font has the CJK glyphs
+        Language::Chinese => 1,
+    };
+
     // Now load the font
     let mut path = PathBuf::from(SDCARD_ROOT);
-    path.push(format!(
-        ".system/res/{}",
-        FONTS[get_font_preference().unwrap_or(0)]
-    ));
+    path.push(format!(".system/res/{}", FONTS[font]));
     println!("Loading font: {}", path.display());
     let mut font_bytes = vec![];
     std::fs::File::open(path)?.read_to_end(&mut font_bytes)?;
@@ -393,12 +842,95 @@ fn load_font() -> Result<FontDefinitions> {
     })
 }
 
+// Wi-Fi signal bars in the top left corner, crossed out while offline
+fn wifi_indicator(ui: &egui::Ui, status: NetworkStatus) {
+    egui::Area::new(egui::Id::new("wifi_indicator"))
+        .anchor(egui::Align2::LEFT_TOP, Vec2::new(scale(2.0), scale(2.0)))
+        .interactable(false)
+        .show(ui.ctx(), |ui| {
+            let size = scale(6.0);
+            let (rect, _response) = ui.allocate_exact_size(Vec2::splat(size), egui::Sense::empty());
+
+            // An unknown signal strength shows all bars
+            let lit_bars = match status {
+                NetworkStatus::Online { signal } => signal.map_or(3, |signal| {
+                    1 + u8::from(signal >= 40) + u8::from(signal >= 70)
+                }),
+                _ => 0,
+            };
+            let bar_width = size / 4.0;
+            for bar in 0..3u8 {
+                let left = rect.left() + f32::from(bar) * bar_width * 1.5;
+                let height = size * f32::from(bar + 1) / 3.0;
+                let color = if bar < lit_bars {
+                    Color32::from_rgb(180, 180, 180)
+                } else {
+                    Color32::from_rgb(60, 60, 60)
+                };
+                ui.painter().rect_filled(
+                    egui::Rect::from_min_max(
+                        egui::pos2(left, rect.bottom() - height),
+                        egui::pos2(left + bar_width, rect.bottom()),
+                    ),
+                    0.0,
+                    color,
+                );
+            }
+
+            if !status.is_online() {
+                let stroke = egui::Stroke::new(scale(1.0), Color32::from_rgb(255, 150, 150));
+                ui.painter()
+                    .line_segment([rect.left_top(), rect.right_bottom()], stroke);
+                ui.painter()
+                    .line_segment([rect.left_bottom(), rect.right_top()], stroke);
+            }
+        });
+}
+
+// Controller button legend in the top right corner, one (button, label) pair each
+fn button_indicator(ui: &egui::Ui, id: &str, buttons: &[(&str, &str)]) {
+    egui::Area::new(egui::Id::new(id))
+        .anchor(egui::Align2::RIGHT_TOP, Vec2::new(scale(-2.0), scale(-2.0)))
+        .interactable(false)
+        .show(ui.ctx(), |ui| {
+            ui.horizontal(|ui| {
+                ui.spacing_mut().item_spacing.x = scale(4.0);
+
+                for (button, label) in buttons {
+                    // Draw circle background for button
+                    let button_size = scale(6.0);
+                    let (rect, _response) =
+                        ui.allocate_exact_size(Vec2::splat(button_size), egui::Sense::empty());
+                    ui.painter().circle(
+                        rect.center(),
+                        button_size / 2.0,
+                        Color32::from_rgb(60, 60, 60),
+                        egui::Stroke::new(scale(1.0), Color32::from_rgb(100, 100, 100)),
+                    );
+                    ui.painter().text(
+                        rect.center(),
+                        egui::Align2::CENTER_CENTER,
+                        *button,
+                        egui::FontId::proportional(button_size),
+                        Color32::from_rgb(180, 180, 180),
+                    );
+
+                    ui.label(
+                        RichText::new(*label)
+                            .size(button_size)
+                            .color(Color32::from_rgb(100, 100, 100)),
+                    );
+                }
+            });
+        });
+}
+
 fn hint_wrap_nextui_tag(app_state: &'static AppStateManager, tag_name: &str) -> String {
     let mut selected_tag = format!("NextUI {tag_name}");
     if !app_state.release_selection_menu() {
         return selected_tag;
     }
-    if !is_most_left_index(app_state) {
+    if !is_most_left_index(app_state) || app_state.nextui_releases_next_page().is_some() {
         selected_tag = format!("<<     {selected_tag}");
     }
     if !is_most_right_index(app_state) {
@@ -420,16 +952,72 @@ fn is_most_right_index(app_state: &'static AppStateManager) -> bool {
     app_state.nextui_releases_and_tags_index() == Some(0)
 }
 
+// The downgrade warning is shown, until accepted, when the release picked in the
+// selector is older than the installed version
+fn downgrade_warning_pending(app_state: &'static AppStateManager) -> bool {
+    if !app_state.release_selection_menu() || app_state.release_selection_confirmed() {
+        return false;
+    }
+    let Some(installed) = app_state.current_version() else {
+        return false;
+    };
+
+    let index = app_state.nextui_releases_and_tags_index().unwrap_or(0);
+    let releases_and_tags = app_state.nextui_releases_and_tags().unwrap_or_default();
+    releases_and_tags.get(index).is_some_and(|selected| {
+        is_older_than_installed(&selected.release, &installed, &releases_and_tags) == Some(true)
+    })
+}
+
+fn open_version_selector(app_state: &'static AppStateManager) {
+    if !matches!(app_state.submenu(), Submenu::NextUI) {
+        return;
+    }
+    app_state.set_release_selection_menu(true);
+    resolve_selected_nextui_release(app_state);
+}
+
+fn open_settings(app_state: &'static AppStateManager) {
+    if !matches!(app_state.submenu(), Submenu::NextUI)
+        || app_state.release_selection_menu()
+        || app_state.current_operation().is_some()
+    {
+        return;
+    }
+    app_state.open_settings();
+}
+
+// Left/Right collapse/expand folders in the file picker, change the focused
+// setting, or else switch versions
+fn handle_horizontal_navigation(app_state: &'static AppStateManager, direction: i32) {
+    match app_state.submenu() {
+        Submenu::NextUI => handle_version_navigation(app_state, direction),
+        Submenu::FilePicker => {
+            app_state.update_file_picker(|picker| picker.expand_focused(direction > 0));
+        }
+        Submenu::Settings => {
+            if let Some(field) = app_state.settings_focus() {
+                change_setting(app_state, field, direction);
+            }
+        }
+        Submenu::InstallPlan | Submenu::Confirmation | Submenu::Offline => {}
+    }
+}
+
 fn handle_version_navigation(app_state: &'static AppStateManager, direction: i32) {
-    if app_state.release_selection_menu() && app_state.release_selection_confirmed() {
+    if app_state.release_selection_menu() {
         let index = app_state.nextui_releases_and_tags_index().unwrap_or(0);
         if direction < 0 && !is_most_left_index(app_state) {
             // Navigate left (older versions)
             app_state.set_nextui_releases_and_tags_index(Some(index + 1));
+        } else if direction < 0 {
+            // Reached the oldest loaded release, fetch the next page if there is one
+            load_more_nextui_releases(app_state);
         } else if direction > 0 && !is_most_right_index(app_state) {
             // Navigate right (newer versions)
             app_state.set_nextui_releases_and_tags_index(Some(index - 1));
         }
+        resolve_selected_nextui_release(app_state);
     }
 }
 
@@ -440,6 +1028,7 @@ pub fn run_ui(
 ) -> Result<()> {
     // Initialize SDL and create window
     let (_sdl_context, window, mut event_pump, _controller) = init_sdl(mock_display_size)?;
+    confirm_self_update();
 
     // Create OpenGL context and egui painter
     let _gl_context = window.gl_create_context()?;
@@ -453,7 +1042,7 @@ pub fn run_ui(
     egui_ctx.set_style(setup_ui_style());
 
     // Font stuff
-    if let Ok(fonts) = load_font() {
+    if let Ok(fonts) = load_font(app_state.settings().language) {
         egui_ctx.set_fonts(fonts);
     }
 
@@ -475,29 +1064,64 @@ pub fn run_ui(
 
                 let title_prefix = format!("NextUI Updater {}", env!("CARGO_PKG_VERSION"));
                 if app_state.release_selection_menu() {
-                    if app_state.release_selection_confirmed() {
+                    if downgrade_warning_pending(app_state) {
                         ui.label(
-                            text(title_prefix + " Version Selector")
+                            text(title_prefix + " Version Selector Warning")
                                 .color(Color32::from_rgb(150, 150, 150)),
                         );
                     } else {
                         ui.label(
-                            text(title_prefix + " Version Selector Warning")
+                            text(title_prefix + " Version Selector")
                                 .color(Color32::from_rgb(150, 150, 150)),
                         );
                     }
+                } else if matches!(app_state.submenu(), Submenu::Settings) {
+                    ui.label(
+                        text(title_prefix + " Settings")
+                            .color(Color32::from_rgb(150, 150, 150)),
+                    );
                 } else {
                     ui.label(
                         text(title_prefix)
                             .color(Color32::from_rgb(150, 150, 150)),
                     );
                 }
+                let mut status = app_state
+                    .device()
+                    .map(|device| device.description())
+                    .into_iter()
+                    .collect::<Vec<_>>();
+                if let Some(battery) = app_state.battery() {
+                    status.push(if battery.charging {
+                        format!("{}% charging", battery.capacity)
+                    } else {
+                        format!("{}%", battery.capacity)
+                    });
+                }
+                if !status.is_empty() {
+                    ui.label(text(status.join(" · ")).color(Color32::from_rgb(150, 150, 150)));
+                }
+                let offline = app_state.network().is_some_and(|s| !s.is_online());
+                if offline && !matches!(app_state.submenu(), Submenu::Offline) {
+                    ui.colored_label(
+                        Color32::from_rgb(255, 200, 120),
+                        text("Offline, showing cached releases"),
+                    );
+                }
                 ui.add_space(scale(4.0));
 
-                ui.add_enabled_ui(!update_in_progress, |ui| {
-                    let submenu = app_state.submenu();
+                let submenu = app_state.submenu();
+                // Prompts shown during an update still take input
+                let awaiting_input =
+                    matches!(submenu, Submenu::InstallPlan | Submenu::Confirmation);
+                ui.add_enabled_ui(!update_in_progress || awaiting_input, |ui| {
                     let menu = match submenu {
                         Submenu::NextUI => nextui_ui(ui, app_state),
+                        Submenu::InstallPlan => install_plan_ui(ui, app_state),
+                        Submenu::FilePicker => file_picker_ui(ui, app_state),
+                        Submenu::Confirmation => confirmation_ui(ui, app_state),
+                        Submenu::Offline => offline_ui(ui, app_state),
+                        Submenu::Settings => settings_ui(ui, app_state),
                     };
 
                     // Focus the first available button for controller navigation
@@ -510,6 +1134,21 @@ pub fn run_ui(
 
                 ui.add_space(scale(8.0));
 
+                // Display overall progress of multi-step operations
+                if let Some(pipeline) = app_state.pipeline_progress() {
+                    ui.label(
+                        text(format!(
+                            "Step {} of {}: {}",
+                            pipeline.step,
+                            pipeline.steps,
+                            pipeline.phase.name()
+                        ))
+                        .color(Color32::from_rgb(150, 150, 150)),
+                    );
+                    ui.add(ProgressBar::new(pipeline.overall).desired_height(scale(3.0)));
+                    ui.add_space(scale(4.0));
+                }
+
                 // Display current operation
                 if let Some(operation) = app_state.current_operation() {
                     ui.label(text(operation).color(Color32::from_rgb(150, 150, 150)));
@@ -536,45 +1175,40 @@ pub fn run_ui(
                             }
                             ui.add(progress_bar);
                         }
+                        Progress::Transfer(transfer) => {
+                            if let Some(pr) = transfer.fraction() {
+                                let mut progress_bar = ProgressBar::new(pr);
+                                if pr > 0.1 {
+                                    progress_bar = progress_bar.show_percentage();
+                                }
+                                ui.add(progress_bar);
+                            } else {
+                                ui.add_space(scale(4.0));
+                                ui.add(Spinner::new().color(Color32::WHITE));
+                            }
+                            ui.label(
+                                text(transfer.summary()).color(Color32::from_rgb(150, 150, 150)),
+                            );
+                        }
                     }
                 }
             });
 
-            if !app_state.release_selection_menu() && app_state.current_operation().is_none() {
-                egui::Area::new(egui::Id::new("version_selector_indicator"))
-                    .anchor(egui::Align2::RIGHT_TOP, Vec2::new(scale(-2.0), scale(-2.0)))
-                    .interactable(false)
-                    .show(ui.ctx(), |ui| {
-                        ui.horizontal(|ui| {
-                            ui.spacing_mut().item_spacing.x = scale(4.0);
-
-                            // Draw circle background for button
-                            let button_size = scale(6.0);
-                            let (rect, _response) = ui.allocate_exact_size(
-                                Vec2::splat(button_size),
-                                egui::Sense::empty(),
-                            );
-                            ui.painter().circle(
-                                rect.center(),
-                                button_size / 2.0,
-                                Color32::from_rgb(60, 60, 60),
-                                egui::Stroke::new(scale(1.0), Color32::from_rgb(100, 100, 100)),
-                            );
-                            ui.painter().text(
-                                rect.center(),
-                                egui::Align2::CENTER_CENTER,
-                                "X",
-                                egui::FontId::proportional(button_size),
-                                Color32::from_rgb(180, 180, 180),
-                            );
+            if let Some(status) = app_state.network() {
+                wifi_indicator(ui, status);
+            }
 
-                            ui.label(
-                                RichText::new("Select Version")
-                                    .size(button_size)
-                                    .color(Color32::from_rgb(100, 100, 100)),
-                            );
-                        });
-                    });
+            if app_state.is_cancellable() {
+                button_indicator(ui, "cancel_indicator", &[("B", "Cancel")]);
+            } else if !app_state.release_selection_menu()
+                && app_state.current_operation().is_none()
+                && matches!(app_state.submenu(), Submenu::NextUI)
+            {
+                button_indicator(
+                    ui,
+                    "menu_indicator",
+                    &[("X", "Select Version"), ("Y", "Settings")],
+                );
             }
 
             if let Some(hint) = app_state.hint() {
@@ -592,13 +1226,13 @@ pub fn run_ui(
                 |ui| {
                     ui.label(
                         RichText::new(
-                            "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789~`!@#$%^&*()-=_+[]{};':\",.<>/?",
+                            "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789~`!@#$%^&*()-=_+[]{};':\",.<>/?·",
                         )
                         .color(Color32::TRANSPARENT)
                     );
                     ui.label(
                         RichText::new(
-                            "XSelect Version",
+                            "XSelect Version YSettings BCancel",
                         )
                         .size(scale(6.0))
                         .color(Color32::TRANSPARENT)
@@ -630,8 +1264,18 @@ pub fn run_ui(
         window.gl_swap_window();
 
         let handle_back_button = || {
-            if app_state.release_selection_menu() {
-                app_state.set_release_selection_menu(false);
+            if app_state.is_cancellable() {
+                app_state.cancel_operation();
+            } else if matches!(app_state.submenu(), Submenu::FilePicker) {
+                app_state.close_file_picker();
+            } else if matches!(app_state.submenu(), Submenu::Settings) {
+                app_state.close_settings();
+            } else if matches!(app_state.submenu(), Submenu::Offline)
+                && app_state.nextui_releases_and_tags().is_some()
+            {
+                app_state.leave_offline();
+            } else if app_state.release_selection_menu() {
+                app_state.leave_release_selection_menu();
             } else {
                 app_state.set_should_quit(true);
             }
@@ -664,17 +1308,20 @@ pub fn run_ui(
                         handle_back_button();
                     }
 
-                    if app_state.release_selection_menu() {
-                        // Handle left/right navigation in selection menu
-                        if button == sdl2::controller::Button::DPadLeft {
-                            handle_version_navigation(app_state, -1);
-                        } else if button == sdl2::controller::Button::DPadRight {
-                            handle_version_navigation(app_state, 1);
-                        }
-                    } else {
+                    if button == sdl2::controller::Button::DPadLeft {
+                        handle_horizontal_navigation(app_state, -1);
+                    } else if button == sdl2::controller::Button::DPadRight {
+                        handle_horizontal_navigation(app_state, 1);
+                    }
+
+                    if !app_state.release_selection_menu() {
                         // Add X button to reach selection menu
                         if button == sdl2::controller::Button::Y {
-                            app_state.set_release_selection_menu(true);
+                            open_version_selector(app_state);
+                        }
+                        // Physical Y button opens the settings
+                        if button == sdl2::controller::Button::X {
+                            open_settings(app_state);
                         }
                     }
 
@@ -694,9 +1341,14 @@ pub fn run_ui(
                 // for easy testing on desktop
                 Event::KeyDown { keycode, .. } => match keycode {
                     Some(sdl2::keyboard::Keycode::Escape) => handle_back_button(),
-                    Some(sdl2::keyboard::Keycode::X) => app_state.set_release_selection_menu(true),
-                    Some(sdl2::keyboard::Keycode::Left) => handle_version_navigation(app_state, -1),
-                    Some(sdl2::keyboard::Keycode::Right) => handle_version_navigation(app_state, 1),
+                    Some(sdl2::keyboard::Keycode::X) => open_version_selector(app_state),
+                    Some(sdl2::keyboard::Keycode::Y) => open_settings(app_state),
+                    Some(sdl2::keyboard::Keycode::Left) => {
+                        handle_horizontal_navigation(app_state, -1);
+                    }
+                    Some(sdl2::keyboard::Keycode::Right) => {
+                        handle_horizontal_navigation(app_state, 1);
+                    }
                     _ => {}
                 },
                 _ => {
@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{atomic_write::write_atomically, Result, SDCARD_ROOT, UPDATER_DATA_DIR};
+
+// A cached HTTP response body along with the validators needed to revalidate it
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CacheEntry {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    #[serde(default)]
+    pub link: Option<String>,
+    pub body: String,
+}
+
+fn cache_path(url: &str) -> PathBuf {
+    // Turn the URL into something that is safe to use as a FAT file name
+    let key: String = url
+        .trim_start_matches("https://")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    PathBuf::from(SDCARD_ROOT)
+        .join(UPDATER_DATA_DIR)
+        .join("cache")
+        .join(format!("{key}.json"))
+}
+
+pub fn load(url: &str) -> Option<CacheEntry> {
+    let contents = std::fs::read_to_string(cache_path(url)).ok()?;
+    let entry: CacheEntry = serde_json::from_str(&contents).ok()?;
+
+    // Guard against (unlikely) key collisions
+    (entry.url == url).then_some(entry)
+}
+
+pub fn store(entry: &CacheEntry) -> Result<()> {
+    write_atomically(
+        &cache_path(&entry.url),
+        serde_json::to_string(entry)?.as_bytes(),
+    )
+}
@@ -0,0 +1,1137 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::File,
+    io::{Cursor, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use bytes::Bytes;
+use tempfile::TempDir;
+
+use crate::{app_state::CancellationToken, Result};
+
+// Records every change made to the target directory, so that a cancelled or
+// failed extraction can be rolled back
+struct ExtractionJournal {
+    // Replaced files are moved here; it lives inside the target directory so
+    // that moving files in and out is a cheap rename on the same filesystem
+    backup_dir: TempDir,
+    created_files: Vec<PathBuf>,
+    created_dirs: Vec<PathBuf>,
+    replaced_files: Vec<(PathBuf, PathBuf)>,
+}
+
+impl ExtractionJournal {
+    fn new(target_directory: &Path) -> Result<Self> {
+        Ok(Self {
+            backup_dir: tempfile::Builder::new()
+                .prefix(".updater-rollback-")
+                .tempdir_in(target_directory)?,
+            created_files: vec![],
+            created_dirs: vec![],
+            replaced_files: vec![],
+        })
+    }
+
+    fn create_dir_all(&mut self, path: &Path) -> Result<()> {
+        let missing: Vec<PathBuf> = path
+            .ancestors()
+            .take_while(|p| !p.exists())
+            .map(Path::to_path_buf)
+            .collect();
+
+        std::fs::create_dir_all(path)?;
+
+        // Outermost first, so rollback can remove them innermost first
+        self.created_dirs.extend(missing.into_iter().rev());
+        Ok(())
+    }
+
+    // Move an existing file out of the way before it gets overwritten
+    fn prepare_write(&mut self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent)?;
+        }
+
+        if path.exists() {
+            let backup = self
+                .backup_dir
+                .path()
+                .join(self.replaced_files.len().to_string());
+            std::fs::rename(path, &backup)?;
+            self.replaced_files.push((path.to_path_buf(), backup));
+        } else {
+            self.created_files.push(path.to_path_buf());
+        }
+
+        Ok(())
+    }
+
+    // Write a file through a temporary sibling which is synced and then renamed
+    // into place, so a partially written file never ends up at `path`
+    fn write_file(
+        &mut self,
+        path: &Path,
+        contents: &mut impl Read,
+        cancellation: &CancellationToken,
+    ) -> Result<u64> {
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent)?;
+        }
+
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| format!("Invalid file path: {}", path.display()))?;
+        let temp_path =
+            path.with_file_name(format!(".{}.updater-tmp", file_name.to_string_lossy()));
+
+        let written = (|| -> Result<u64> {
+            let mut file = File::create(&temp_path)?;
+            let written = copy_bounded(contents, &mut file, cancellation)?;
+            file.sync_all()?;
+            Ok(written)
+        })();
+
+        let result = written.and_then(|written| {
+            self.prepare_write(path)?;
+            std::fs::rename(&temp_path, path)?;
+            Ok(written)
+        });
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp_path);
+        }
+        result
+    }
+
+    fn create_symlink(&mut self, path: &Path, target: &Path) -> Result<()> {
+        self.prepare_write(path)?;
+        create_symlink(target, path)?;
+        Ok(())
+    }
+
+    // Remove a file, keeping it around until the extraction is known to succeed
+    fn remove_file(&mut self, path: &Path) -> Result<()> {
+        if path.is_file() {
+            self.prepare_write(path)?;
+        }
+        Ok(())
+    }
+
+    fn rollback(self) {
+        println!("Rolling back extraction...");
+
+        for path in self.created_files.iter().rev() {
+            if let Err(err) = std::fs::remove_file(path) {
+                println!("Failed to remove {}: {err}", path.display());
+            }
+        }
+
+        for (original, backup) in self.replaced_files.iter().rev() {
+            // The new file may or may not have been written yet
+            let _ = std::fs::remove_file(original);
+            if let Err(err) = std::fs::rename(backup, original) {
+                println!("Failed to restore {}: {err}", original.display());
+            }
+        }
+
+        for path in self.created_dirs.iter().rev() {
+            // Only succeeds for directories which are empty again
+            let _ = std::fs::remove_dir(path);
+        }
+    }
+}
+
+// Locations holding user data. Archive entries there are skipped unless the
+// release manifest explicitly allows them. Matched case-insensitively, as SD
+// cards are usually FAT/exFAT formatted.
+const PROTECTED_LOCATIONS: [&str; 3] = [".userdata", "Saves", "Bios"];
+
+// What an archive is allowed to write
+#[derive(Default, Clone, Debug)]
+pub struct ExtractPolicy {
+    // Path prefixes inside PROTECTED_LOCATIONS the archive may write to,
+    // e.g. "Bios/" or ".userdata/shared/"
+    pub allowed_protected: Vec<String>,
+    // Files modified by the user, which are left untouched
+    pub keep: HashSet<PathBuf>,
+    // Files modified by the user, which are saved as "<name>.bak" before being replaced
+    pub backup: HashSet<PathBuf>,
+    // Previously installed files the archive no longer contains, removed after
+    // a copy is saved below `removal_backup_dir`
+    pub remove: Vec<PathBuf>,
+    pub removal_backup_dir: PathBuf,
+}
+
+// Error for archives containing an entry the policy refuses
+#[derive(Debug)]
+pub struct UnsafeEntry {
+    pub entry: String,
+    pub reason: String,
+}
+
+impl fmt::Display for UnsafeEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Refusing archive, entry {:?} {}",
+            self.entry, self.reason
+        )
+    }
+}
+
+impl std::error::Error for UnsafeEntry {}
+
+fn unsafe_entry(
+    entry: &str,
+    reason: impl Into<String>,
+) -> Box<dyn std::error::Error + Send + Sync> {
+    Box::new(UnsafeEntry {
+        entry: entry.to_owned(),
+        reason: reason.into(),
+    })
+}
+
+impl ExtractPolicy {
+    // Relative path an entry extracts to, refusing anything that could land
+    // outside the target directory
+    pub fn entry_path(name: &str) -> Result<PathBuf> {
+        // Some zip tools write Windows separators
+        let normalized = name.replace('\\', "/");
+
+        if normalized.contains('\0') {
+            return Err(unsafe_entry(name, "contains a NUL byte"));
+        }
+        let has_drive_prefix = normalized.as_bytes().get(1) == Some(&b':');
+        if normalized.starts_with('/') || has_drive_prefix {
+            return Err(unsafe_entry(name, "is an absolute path"));
+        }
+
+        let mut path = PathBuf::new();
+        for component in normalized.split('/') {
+            match component {
+                "" | "." => {}
+                ".." => {
+                    return Err(unsafe_entry(
+                        name,
+                        "traverses out of its folder with \"..\"",
+                    ))
+                }
+                other => path.push(other),
+            }
+        }
+
+        if path.as_os_str().is_empty() {
+            return Err(unsafe_entry(name, "has an empty path"));
+        }
+
+        Ok(path)
+    }
+
+    // Symlinks must point somewhere inside the target directory. Returns the
+    // path the link resolves to, relative to the target directory.
+    fn check_symlink(name: &str, path: &Path, target: &str) -> Result<PathBuf> {
+        let target = target.replace('\\', "/");
+        if target.starts_with('/') || target.as_bytes().get(1) == Some(&b':') {
+            return Err(unsafe_entry(
+                name,
+                format!("is a symlink to the absolute path {target:?}"),
+            ));
+        }
+
+        // Follow the link's components from the folder it is in
+        let mut resolved = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        for component in target.split('/') {
+            match component {
+                "" | "." => {}
+                ".." => {
+                    if !resolved.pop() {
+                        return Err(unsafe_entry(
+                            name,
+                            format!("is a symlink escaping the SD card ({target:?})"),
+                        ));
+                    }
+                }
+                other => resolved.push(other),
+            }
+        }
+
+        Ok(resolved)
+    }
+
+    // Entries are resolved as if every folder was real, which only holds if
+    // nothing is written through a symlink the archive creates
+    fn check_not_through_symlink(
+        name: &str,
+        path: &Path,
+        symlinks: &HashSet<PathBuf>,
+    ) -> Result<()> {
+        match path.ancestors().skip(1).find(|a| symlinks.contains(*a)) {
+            Some(link) => Err(unsafe_entry(
+                name,
+                format!("is inside the symlink {}", link.display()),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    // Whether the archive may write `path`: anything outside PROTECTED_LOCATIONS,
+    // and inside them only the prefixes the release manifest allows
+    pub fn allows(&self, path: &Path) -> bool {
+        let Some(first) = path.components().next() else {
+            return true;
+        };
+        let first = first.as_os_str().to_string_lossy();
+        if !PROTECTED_LOCATIONS
+            .iter()
+            .any(|location| location.eq_ignore_ascii_case(&first))
+        {
+            return true;
+        }
+
+        let path = path.to_string_lossy().to_lowercase();
+        self.allowed_protected.iter().any(|prefix| {
+            let prefix = prefix.trim_start_matches('/').to_lowercase();
+            !prefix.is_empty() && path.starts_with(&prefix)
+        })
+    }
+}
+
+// Part of an extraction that progress is reported for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtractStage {
+    // Saving copies of the modified files replaced and the files removed
+    Backup,
+    Extract,
+}
+
+// What an extraction did, and what it could not do
+#[derive(Default, Debug)]
+pub struct ExtractReport {
+    // Entries whose Unix metadata could not be applied: errors, executable bits
+    // lost and symlinks not created, e.g. on a FAT/exFAT formatted SD card
+    pub metadata_not_applied: Vec<String>,
+    // Relative path and CRC32 of every regular file written
+    pub written: Vec<(PathBuf, u32)>,
+    pub kept: Vec<PathBuf>,
+    pub backed_up: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    // Entries left out because they are in (or link into) a protected location
+    pub skipped_protected: Vec<PathBuf>,
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn create_symlink(_target: &Path, _link: &Path) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+// Apply the permission bits of a zip entry; false if the filesystem did not keep them
+#[cfg(unix)]
+fn apply_unix_mode(path: &Path, mode: u32) -> std::io::Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = mode & 0o7777;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+
+    // FAT/exFAT may accept a chmod without storing anything
+    Ok(std::fs::symlink_metadata(path)?.permissions().mode() & 0o777 == mode & 0o777)
+}
+
+#[cfg(not(unix))]
+fn apply_unix_mode(_path: &Path, _mode: u32) -> std::io::Result<bool> {
+    Ok(false)
+}
+
+// Whether files in `dir` keep the permission bits they are given. FAT/exFAT
+// mounts show the same mode for every file, whatever chmod was asked for.
+#[cfg(unix)]
+fn keeps_unix_modes(dir: &Path) -> bool {
+    let probe = dir.join(".mode-probe");
+    let keeps = File::create(&probe).is_ok()
+        && [0o600, 0o751]
+            .iter()
+            .all(|&mode| apply_unix_mode(&probe, mode).unwrap_or(false));
+    let _ = std::fs::remove_file(&probe);
+    if !keeps {
+        println!(
+            "{} doesn't keep Unix modes, leaving them to the mount",
+            dir.display()
+        );
+    }
+    keeps
+}
+
+#[cfg(not(unix))]
+fn keeps_unix_modes(_dir: &Path) -> bool {
+    false
+}
+
+// Whether the owner may execute `path`
+#[cfg(unix)]
+fn is_executable(path: &Path) -> std::io::Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Ok(std::fs::metadata(path)?.permissions().mode() & 0o100 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> std::io::Result<bool> {
+    Ok(true)
+}
+
+// Resolve "." and ".." components without touching the filesystem
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+// Copy through a fixed size buffer, so large entries don't have to fit in memory
+fn copy_bounded(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    cancellation: &CancellationToken,
+) -> Result<u64> {
+    const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+    let mut buffer = vec![0; COPY_BUFFER_SIZE];
+    let mut copied: u64 = 0;
+
+    loop {
+        cancellation.check()?;
+
+        let bytes_read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        // write_all retries short writes instead of silently dropping data
+        writer.write_all(&buffer[..bytes_read])?;
+        copied += bytes_read as u64;
+    }
+
+    Ok(copied)
+}
+
+// Check that `bytes` is a zip archive with a readable central directory
+pub fn verify_zip(bytes: &Bytes) -> Result<()> {
+    let archive = zip::ZipArchive::new(Cursor::new(bytes.clone()))?;
+    if archive.is_empty() {
+        return Err("Archive is empty".into());
+    }
+    Ok(())
+}
+
+// Relative path and CRC32 of the regular files `extract_zip` would write
+pub fn planned_files<T: Fn(&str) -> bool>(
+    bytes: &Bytes,
+    policy: &ExtractPolicy,
+    filter: T,
+) -> Result<Vec<(PathBuf, u32)>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes.clone()))?;
+    let (selected, _, _) = select_entries(&mut archive, policy, filter)?;
+
+    let mut files = vec![];
+    for (file_number, relative_path, symlink_target) in selected {
+        let entry = archive.by_index_raw(file_number)?;
+        if !entry.is_dir() && symlink_target.is_none() {
+            files.push((relative_path, entry.crc32()));
+        }
+    }
+    Ok(files)
+}
+
+pub fn extract_zip<T: Fn(&str) -> bool>(
+    bytes: Bytes,
+    target_directory: &Path,
+    policy: &ExtractPolicy,
+    filter: T,
+    progress_cb: impl Fn(ExtractStage, f32),
+    cancellation: &CancellationToken,
+) -> Result<ExtractReport> {
+    let mut journal = ExtractionJournal::new(target_directory)?;
+
+    match extract_entries(
+        bytes,
+        target_directory,
+        policy,
+        filter,
+        progress_cb,
+        cancellation,
+        &mut journal,
+    ) {
+        Ok(report) => {
+            remove_empty_parents(&report.removed, target_directory);
+            Ok(report)
+        }
+        Err(err) => {
+            journal.rollback();
+            Err(err)
+        }
+    }
+}
+
+// Before anything is extracted, save modified files about to be replaced as
+// "<name>.bak" and copy the files dropped upstream below `removal_backup_dir`
+fn back_up_files(
+    selected: &[SelectedEntry],
+    target_directory: &Path,
+    policy: &ExtractPolicy,
+    journal: &mut ExtractionJournal,
+    cancellation: &CancellationToken,
+    progress_cb: impl Fn(f32),
+    report: &mut ExtractReport,
+) -> Result<()> {
+    // (relative path, copy, whether the file is a modified one)
+    let mut backups = vec![];
+    for (_, relative_path, _) in selected {
+        let path = target_directory.join(relative_path);
+        if policy.backup.contains(relative_path) && path.is_file() {
+            let mut backup_name = path.into_os_string();
+            backup_name.push(".bak");
+            backups.push((relative_path, PathBuf::from(backup_name), true));
+        }
+    }
+    for relative_path in &policy.remove {
+        if target_directory.join(relative_path).is_file() {
+            let backup_path = policy.removal_backup_dir.join(relative_path);
+            backups.push((relative_path, backup_path, false));
+        }
+    }
+
+    for (done, (relative_path, backup_path, modified)) in backups.iter().enumerate() {
+        cancellation.check()?;
+        journal.write_file(
+            backup_path,
+            &mut File::open(target_directory.join(relative_path))?,
+            cancellation,
+        )?;
+        println!("Backed up file: {}", backup_path.display());
+        if *modified {
+            report.backed_up.push((*relative_path).clone());
+        }
+        progress_cb((done + 1) as f32 / backups.len() as f32);
+    }
+
+    progress_cb(1.0);
+    Ok(())
+}
+
+// Remove the files dropped upstream, once back_up_files saved a copy of each
+fn remove_dropped_files(
+    policy: &ExtractPolicy,
+    target_directory: &Path,
+    journal: &mut ExtractionJournal,
+    cancellation: &CancellationToken,
+    report: &mut ExtractReport,
+) -> Result<()> {
+    for relative_path in &policy.remove {
+        cancellation.check()?;
+        let path = target_directory.join(relative_path);
+        if !path.is_file() {
+            continue;
+        }
+
+        journal.remove_file(&path)?;
+        println!("Removed file: {}", path.display());
+        report.removed.push(relative_path.clone());
+    }
+
+    Ok(())
+}
+
+// Clean up directories left empty by removed files, e.g. the folder of a pak
+// dropped upstream, which would otherwise still show up in the menu
+fn remove_empty_parents(removed: &[PathBuf], target_directory: &Path) {
+    for relative_path in removed {
+        for parent in relative_path.ancestors().skip(1) {
+            if parent.as_os_str().is_empty() {
+                break;
+            }
+            // Only succeeds for empty directories
+            if std::fs::remove_dir(target_directory.join(parent)).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+// Reader adapter reporting the number of bytes read so far
+struct ProgressReader<R, F> {
+    inner: R,
+    read: u64,
+    progress_cb: F,
+}
+
+impl<R: Read, F: FnMut(u64)> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        (self.progress_cb)(self.read);
+        Ok(n)
+    }
+}
+
+// Entry index, relative path and symlink target of an entry to extract
+type SelectedEntry = (usize, PathBuf, Option<PathBuf>);
+
+// Check every entry against the policy and pick the ones to extract up front,
+// so nothing is written for an unsafe archive, and so progress can be based on
+// the uncompressed size rather than on the number of entries. Also returns the
+// entries skipped for being in a protected location.
+fn select_entries<T: Fn(&str) -> bool>(
+    archive: &mut zip::ZipArchive<Cursor<Bytes>>,
+    policy: &ExtractPolicy,
+    filter: T,
+) -> Result<(Vec<SelectedEntry>, u64, Vec<PathBuf>)> {
+    const MAX_SYMLINK_TARGET_LEN: u64 = 4096;
+
+    let mut selected = vec![];
+    let mut total_bytes: u64 = 0;
+    // Names of the selected entries, for errors, and the symlinks among them
+    let mut names = vec![];
+    let mut symlinks = HashSet::new();
+    let mut skipped_protected = vec![];
+    for file_number in 0..archive.len() {
+        let (name, is_dir, is_symlink, size) = {
+            let entry = archive.by_index_raw(file_number)?;
+            (
+                entry.name().to_owned(),
+                entry.is_dir(),
+                entry.is_symlink(),
+                entry.size(),
+            )
+        };
+        let relative_path = ExtractPolicy::entry_path(&name)?;
+
+        let symlink_target = if is_symlink {
+            if size > MAX_SYMLINK_TARGET_LEN {
+                return Err(unsafe_entry(&name, "is a symlink with an overlong target"));
+            }
+            let mut target = String::new();
+            archive.by_index(file_number)?.read_to_string(&mut target)?;
+            let resolved = ExtractPolicy::check_symlink(&name, &relative_path, &target)?;
+            Some((PathBuf::from(target), resolved))
+        } else {
+            None
+        };
+
+        if !filter(relative_path.as_os_str().to_string_lossy().as_ref()) {
+            println!("Skipping file: {}", relative_path.display());
+            continue;
+        }
+
+        // A link into a protected location would let the archive write there
+        let protected = (!is_dir && !policy.allows(&relative_path))
+            || symlink_target
+                .as_ref()
+                .is_some_and(|(_, resolved)| !policy.allows(resolved));
+        if protected {
+            println!("Skipping protected file: {}", relative_path.display());
+            skipped_protected.push(relative_path);
+            continue;
+        }
+        let symlink_target = symlink_target.map(|(target, _)| {
+            symlinks.insert(relative_path.clone());
+            target
+        });
+        names.push(name);
+
+        if !is_dir && !is_symlink {
+            total_bytes += size;
+        }
+        selected.push((file_number, relative_path, symlink_target));
+    }
+
+    for ((_, path, _), name) in selected.iter().zip(&names) {
+        ExtractPolicy::check_not_through_symlink(name, path, &symlinks)?;
+    }
+
+    Ok((selected, total_bytes, skipped_protected))
+}
+
+fn extract_entries<T: Fn(&str) -> bool>(
+    bytes: Bytes,
+    target_directory: &Path,
+    policy: &ExtractPolicy,
+    filter: T,
+    progress_cb: impl Fn(ExtractStage, f32),
+    cancellation: &CancellationToken,
+    journal: &mut ExtractionJournal,
+) -> Result<ExtractReport> {
+    // Extract the update package
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let (selected, total_bytes, skipped_protected) = select_entries(&mut archive, policy, filter)?;
+
+    let mut report = ExtractReport {
+        skipped_protected,
+        ..ExtractReport::default()
+    };
+    let keeps_modes = keeps_unix_modes(journal.backup_dir.path());
+    back_up_files(
+        &selected,
+        target_directory,
+        policy,
+        journal,
+        cancellation,
+        |pr| progress_cb(ExtractStage::Backup, pr),
+        &mut report,
+    )?;
+
+    let mut extracted_bytes: u64 = 0;
+    let report_progress = |done: u64| {
+        if total_bytes > 0 {
+            progress_cb(
+                ExtractStage::Extract,
+                (done as f64 / total_bytes as f64).min(1.0) as f32,
+            );
+        }
+    };
+
+    // Applied last, so read-only directories don't block extraction into them
+    let mut directory_modes = vec![];
+    // Created last, so the fallback for filesystems without symlinks can copy the target
+    let mut symlinks = vec![];
+
+    for (file_number, relative_path, symlink_target) in selected {
+        cancellation.check()?;
+
+        let mut next = archive.by_index(file_number)?;
+
+        let unix_mode = next.unix_mode();
+
+        if next.is_dir() {
+            let extracted_folder_path = target_directory.join(relative_path);
+            journal.create_dir_all(&extracted_folder_path)?;
+            println!("Created directory: {}", extracted_folder_path.display());
+            if let Some(mode) = unix_mode.filter(|_| keeps_modes) {
+                directory_modes.push((extracted_folder_path, mode));
+            }
+        } else if let Some(target) = symlink_target {
+            symlinks.push((target_directory.join(relative_path), target));
+        } else if next.is_file() {
+            let entry_size = next.size();
+            let extracted_file_path = target_directory.join(&relative_path);
+
+            if policy.keep.contains(&relative_path) {
+                println!("Keeping modified file: {}", extracted_file_path.display());
+                report.kept.push(relative_path);
+                extracted_bytes += entry_size;
+                report_progress(extracted_bytes);
+                continue;
+            }
+
+            // Report progress while large entries are still being decompressed
+            let mut reader = ProgressReader {
+                inner: &mut next,
+                read: 0,
+                progress_cb: |read: u64| report_progress(extracted_bytes + read.min(entry_size)),
+            };
+            let written = journal.write_file(&extracted_file_path, &mut reader, cancellation)?;
+            if written != entry_size {
+                return Err(format!(
+                    "Extracted {} bytes of {} for {}",
+                    written,
+                    entry_size,
+                    extracted_file_path.display()
+                )
+                .into());
+            }
+            println!("Extracted file: {}", extracted_file_path.display());
+            report.written.push((relative_path, next.crc32()));
+
+            if let Some(mode) = unix_mode {
+                apply_mode_or_report(&extracted_file_path, mode, keeps_modes, &mut report);
+            }
+
+            extracted_bytes += entry_size;
+            report_progress(extracted_bytes);
+        }
+    }
+
+    for (link_path, target) in symlinks {
+        cancellation.check()?;
+        extract_symlink(
+            &link_path,
+            &target,
+            target_directory,
+            journal,
+            cancellation,
+            &mut report,
+        )?;
+    }
+
+    remove_dropped_files(policy, target_directory, journal, cancellation, &mut report)?;
+
+    for (path, mode) in directory_modes.iter().rev() {
+        apply_mode_or_report(path, *mode, keeps_modes, &mut report);
+    }
+
+    progress_cb(ExtractStage::Extract, 1.0);
+
+    if !report.metadata_not_applied.is_empty() {
+        println!(
+            "Unix metadata could not be applied to {} entries: {:?}",
+            report.metadata_not_applied.len(),
+            report.metadata_not_applied
+        );
+    }
+
+    Ok(report)
+}
+
+// Without modes (FAT/exFAT) the mount decides them, and only a lost executable
+// bit is worth reporting
+fn apply_mode_or_report(path: &Path, mode: u32, keeps_modes: bool, report: &mut ExtractReport) {
+    if !keeps_modes {
+        report_lost_executable(path, mode, report);
+        return;
+    }
+    match apply_unix_mode(path, mode) {
+        Ok(true) => {}
+        Ok(false) => report.metadata_not_applied.push(format!(
+            "{} (mode {:o} not kept)",
+            path.display(),
+            mode & 0o7777
+        )),
+        Err(err) => report.metadata_not_applied.push(format!(
+            "{} (mode {:o}: {err})",
+            path.display(),
+            mode & 0o7777
+        )),
+    }
+}
+
+fn report_lost_executable(path: &Path, mode: u32, report: &mut ExtractReport) {
+    if mode & 0o111 == 0 {
+        return;
+    }
+    match is_executable(path) {
+        Ok(true) => {}
+        Ok(false) => report
+            .metadata_not_applied
+            .push(format!("{} (executable bit lost)", path.display())),
+        Err(err) => report.metadata_not_applied.push(format!(
+            "{} (mode {:o}: {err})",
+            path.display(),
+            mode & 0o7777
+        )),
+    }
+}
+
+// Create a symlink, or on filesystems without symlink support (FAT/exFAT)
+// fall back to a copy of the target file
+fn extract_symlink(
+    link_path: &Path,
+    target: &Path,
+    target_directory: &Path,
+    journal: &mut ExtractionJournal,
+    cancellation: &CancellationToken,
+    report: &mut ExtractReport,
+) -> Result<()> {
+    let Err(err) = journal.create_symlink(link_path, target) else {
+        println!(
+            "Created symlink: {} -> {}",
+            link_path.display(),
+            target.display()
+        );
+        return Ok(());
+    };
+    println!(
+        "Failed to create symlink {}: {err}, copying target instead",
+        link_path.display()
+    );
+
+    let resolved = normalize_path(&link_path.parent().unwrap_or(target_directory).join(target));
+    if resolved.starts_with(normalize_path(target_directory)) && resolved.is_file() {
+        let mut source = File::open(&resolved)?;
+        journal.write_file(link_path, &mut source, cancellation)?;
+        report.metadata_not_applied.push(format!(
+            "{} (symlink to {} replaced by a copy)",
+            link_path.display(),
+            target.display()
+        ));
+    } else {
+        report.metadata_not_applied.push(format!(
+            "{} (symlink to {} not created: {err})",
+            link_path.display(),
+            target.display()
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn error_reason(result: Result<impl fmt::Debug>) -> String {
+        let err = result.expect_err("entry should be refused");
+        err.downcast_ref::<UnsafeEntry>()
+            .expect("error should be an UnsafeEntry")
+            .reason
+            .clone()
+    }
+
+    // Archive of (name, symlink target) entries, regular files without a target
+    fn archive(entries: &[(&str, Option<&str>)]) -> zip::ZipArchive<Cursor<Bytes>> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, target) in entries {
+            if let Some(target) = target {
+                zip.add_symlink(*name, *target, options).unwrap();
+            } else {
+                zip.start_file(*name, options).unwrap();
+                zip.write_all(b"contents").unwrap();
+            }
+        }
+        let bytes = Bytes::from(zip.finish().unwrap().into_inner());
+        zip::ZipArchive::new(Cursor::new(bytes)).unwrap()
+    }
+
+    fn select(entries: &[(&str, Option<&str>)]) -> Result<Vec<SelectedEntry>> {
+        select_entries(&mut archive(entries), &ExtractPolicy::default(), |_| true)
+            .map(|(selected, _, _)| selected)
+    }
+
+    #[test]
+    fn backs_up_files_before_extracting() {
+        let target = tempfile::tempdir().unwrap();
+        std::fs::write(target.path().join("config.txt"), "mine").unwrap();
+        std::fs::write(target.path().join("dropped.txt"), "old").unwrap();
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("config.txt", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"new").unwrap();
+        let bytes = Bytes::from(zip.finish().unwrap().into_inner());
+
+        let policy = ExtractPolicy {
+            backup: HashSet::from([PathBuf::from("config.txt")]),
+            remove: vec![PathBuf::from("dropped.txt")],
+            removal_backup_dir: target.path().join("removed"),
+            ..ExtractPolicy::default()
+        };
+        let stages = std::cell::RefCell::new(vec![]);
+        let report = extract_zip(
+            bytes,
+            target.path(),
+            &policy,
+            |_| true,
+            |stage, _| stages.borrow_mut().push(stage),
+            &CancellationToken::default(),
+        )
+        .unwrap();
+
+        let read = |path: &str| std::fs::read_to_string(target.path().join(path)).unwrap();
+        assert_eq!(read("config.txt"), "new");
+        assert_eq!(read("config.txt.bak"), "mine");
+        assert_eq!(read("removed/dropped.txt"), "old");
+        assert!(!target.path().join("dropped.txt").exists());
+        assert_eq!(report.backed_up, vec![PathBuf::from("config.txt")]);
+        assert_eq!(report.removed, vec![PathBuf::from("dropped.txt")]);
+
+        // Every backup is reported before extraction starts
+        let stages = stages.into_inner();
+        let first_extract = stages.iter().position(|&s| s == ExtractStage::Extract);
+        assert!(first_extract.is_some_and(|index| index > 0));
+        assert!(stages[first_extract.unwrap()..]
+            .iter()
+            .all(|&s| s == ExtractStage::Extract));
+    }
+
+    #[test]
+    fn entry_path_normalizes_separators_and_dots() {
+        assert_eq!(
+            ExtractPolicy::entry_path("Tools\\tg5040/./app.pak/launch.sh").unwrap(),
+            PathBuf::from("Tools/tg5040/app.pak/launch.sh")
+        );
+        assert_eq!(
+            ExtractPolicy::entry_path("Roms//GBA/").unwrap(),
+            PathBuf::from("Roms/GBA")
+        );
+    }
+
+    #[test]
+    fn entry_path_refuses_escaping_names() {
+        assert!(error_reason(ExtractPolicy::entry_path("../autorun.inf")).contains(".."));
+        assert!(error_reason(ExtractPolicy::entry_path("Roms/../../x")).contains(".."));
+        assert!(error_reason(ExtractPolicy::entry_path("/etc/passwd")).contains("absolute"));
+        assert!(error_reason(ExtractPolicy::entry_path("C:\\boot.ini")).contains("absolute"));
+        assert!(error_reason(ExtractPolicy::entry_path("a\0b")).contains("NUL"));
+        assert!(error_reason(ExtractPolicy::entry_path("./")).contains("empty"));
+    }
+
+    #[test]
+    fn check_symlink_resolves_inside_target() {
+        let resolved =
+            ExtractPolicy::check_symlink("a/b/link", Path::new("a/b/link"), "../c/./d").unwrap();
+        assert_eq!(resolved, PathBuf::from("a/c/d"));
+
+        let resolved = ExtractPolicy::check_symlink("a/link", Path::new("a/link"), "..").unwrap();
+        assert_eq!(resolved, PathBuf::new());
+    }
+
+    #[test]
+    fn check_symlink_refuses_escaping_targets() {
+        let escape = |path: &str, target: &str| {
+            error_reason(ExtractPolicy::check_symlink(path, Path::new(path), target))
+        };
+        assert!(escape("link", "..").contains("escaping"));
+        assert!(escape("a/link", "../../x").contains("escaping"));
+        assert!(escape("a/link", "b/../../../x").contains("escaping"));
+        assert!(escape("a/link", "/mnt/SDCARD").contains("absolute"));
+        assert!(escape("a/link", "C:\\Windows").contains("absolute"));
+    }
+
+    #[test]
+    fn refuses_entries_through_archive_symlinks() {
+        // a/b points at the root, so a/b/c -> .. would point above it
+        let reason = error_reason(select(&[("a/b", Some("..")), ("a/b/c", Some(".."))]));
+        assert!(reason.contains("inside the symlink"));
+
+        // The order of the entries doesn't matter
+        let reason = error_reason(select(&[("a/b/file", None), ("a/b", Some("x"))]));
+        assert!(reason.contains("inside the symlink"));
+
+        assert!(select(&[("a/b", Some("..")), ("a/c", None)]).is_ok());
+    }
+
+    #[test]
+    fn skips_entries_in_protected_locations() {
+        let paths = |entries: &[SelectedEntry]| -> Vec<PathBuf> {
+            entries.iter().map(|(_, path, _)| path.clone()).collect()
+        };
+        let mut entries = archive(&[
+            ("Bios/GBA/gba_bios.bin", None),
+            ("saves/x.sav", None),
+            ("x", Some("Saves")),
+            ("Tools/x", Some("../.userdata/shared")),
+            ("Tools/y", Some("../Roms")),
+        ]);
+        let (selected, _, skipped) =
+            select_entries(&mut entries, &ExtractPolicy::default(), |_| true).unwrap();
+        assert_eq!(paths(&selected), vec![PathBuf::from("Tools/y")]);
+        assert_eq!(
+            skipped,
+            vec![
+                PathBuf::from("Bios/GBA/gba_bios.bin"),
+                PathBuf::from("saves/x.sav"),
+                PathBuf::from("x"),
+                PathBuf::from("Tools/x"),
+            ]
+        );
+
+        let policy = ExtractPolicy {
+            allowed_protected: vec!["Bios/".to_string()],
+            ..ExtractPolicy::default()
+        };
+        let mut entries = archive(&[("x", Some("Bios/GBA")), ("Bios/readme.txt", None)]);
+        let (selected, _, skipped) = select_entries(&mut entries, &policy, |_| true).unwrap();
+        assert_eq!(selected.len(), 2);
+        assert!(skipped.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn applies_modes_where_the_filesystem_keeps_them() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let target = tempfile::tempdir().unwrap();
+        assert!(keeps_unix_modes(target.path()));
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+        for (name, mode) in [("launch.sh", 0o755), ("readme.txt", 0o644)] {
+            let options = zip::write::SimpleFileOptions::default().unix_permissions(mode);
+            zip.start_file(name, options).unwrap();
+            zip.write_all(b"contents").unwrap();
+        }
+        let bytes = Bytes::from(zip.finish().unwrap().into_inner());
+
+        let report = extract_zip(
+            bytes,
+            target.path(),
+            &ExtractPolicy::default(),
+            |_| true,
+            |_, _| {},
+            &CancellationToken::default(),
+        )
+        .unwrap();
+
+        let mode = |name: &str| {
+            let metadata = std::fs::metadata(target.path().join(name)).unwrap();
+            metadata.permissions().mode() & 0o777
+        };
+        assert_eq!(mode("launch.sh"), 0o755);
+        assert_eq!(mode("readme.txt"), 0o644);
+        assert!(report.metadata_not_applied.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reports_only_lost_executable_bits_without_modes() {
+        let target = tempfile::tempdir().unwrap();
+        let path = target.path().join("launch.sh");
+        std::fs::write(&path, "#!/bin/sh").unwrap();
+        apply_unix_mode(&path, 0o644).unwrap();
+
+        let mut report = ExtractReport::default();
+        apply_mode_or_report(&path, 0o644, false, &mut report);
+        assert!(report.metadata_not_applied.is_empty());
+
+        apply_mode_or_report(&path, 0o755, false, &mut report);
+        assert_eq!(report.metadata_not_applied.len(), 1);
+        assert!(report.metadata_not_applied[0].contains("executable bit lost"));
+
+        // The mount's mode is left alone
+        assert!(!is_executable(&path).unwrap());
+    }
+
+    #[test]
+    fn full_update_skips_bios_files() {
+        let target = tempfile::tempdir().unwrap();
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+        for name in ["Bios/readme.txt", "MinUI.zip"] {
+            zip.start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(b"contents").unwrap();
+        }
+        let bytes = Bytes::from(zip.finish().unwrap().into_inner());
+
+        let profile = &crate::profiles::Profile::defaults()[1];
+        let report = extract_zip(
+            bytes,
+            target.path(),
+            &ExtractPolicy::default(),
+            profile.file_filter(None),
+            |_, _| {},
+            &CancellationToken::default(),
+        )
+        .unwrap();
+
+        assert!(target.path().join("MinUI.zip").is_file());
+        assert!(!target.path().join("Bios/readme.txt").exists());
+        assert_eq!(
+            report.skipped_protected,
+            vec![PathBuf::from("Bios/readme.txt")]
+        );
+    }
+}
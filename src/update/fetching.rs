@@ -1,19 +1,40 @@
 use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
 use const_format::concatcp;
 use reqwest::blocking::Client;
-use reqwest::IntoUrl;
+use reqwest::{header, IntoUrl, StatusCode};
+use serde::de::DeserializeOwned;
 
-use crate::github::{Release, Tag};
+use super::cache::{self, CacheEntry};
+use crate::app_state::{CancellationToken, TransferProgress};
+use crate::github::{Commit, GitRef, GitTag, Release, ReleaseManifest, Tag, MANIFEST_ASSET_NAME};
+use crate::settings::TlsPolicy;
 use crate::Result;
 
 const USER_AGENT: &str = concatcp!("NextUIUpdater/", env!("CARGO_PKG_VERSION"));
 
 static CLIENT_CELL: OnceLock<Client> = OnceLock::new();
+static STRICT_CLIENT_CELL: OnceLock<Client> = OnceLock::new();
+static ACCEPT_INVALID_CERTS: AtomicBool = AtomicBool::new(true);
+
+pub fn set_tls_policy(policy: TlsPolicy) {
+    ACCEPT_INVALID_CERTS.store(policy == TlsPolicy::AcceptInvalid, Ordering::Relaxed);
+}
 
 fn get_client() -> &'static Client {
+    if !ACCEPT_INVALID_CERTS.load(Ordering::Relaxed) {
+        return STRICT_CLIENT_CELL.get_or_init(|| {
+            reqwest::blocking::Client::builder()
+                .timeout(None)
+                .build()
+                .expect("Failed to create HTTP client")
+        });
+    }
+
     CLIENT_CELL.get_or_init(|| {
         reqwest::blocking::Client::builder()
             .danger_accept_invalid_certs(true)
@@ -24,70 +45,298 @@ fn get_client() -> &'static Client {
     })
 }
 
-pub fn fetch_latest_release(repo: &str) -> Result<Release> {
-    let response = get_client()
-        .get(format!(
-            "https://api.github.com/repos/{repo}/releases/latest"
-        ))
-        .header("User-Agent", USER_AGENT)
-        .send()?;
+// A page of a paginated GitHub API listing
+pub struct Page<T> {
+    pub items: Vec<T>,
+    // URL of the following page, from the `Link: <...>; rel="next"` header
+    pub next: Option<String>,
+}
+
+fn parse_next_link(link: &str) -> Option<String> {
+    // Link: <https://api.github.com/...&page=2>; rel="next", <...&page=5>; rel="last"
+    link.split(',').find_map(|part| {
+        let (url, params) = part.split_once(';')?;
+        params
+            .split(';')
+            .any(|param| param.trim() == "rel=\"next\"")
+            .then(|| {
+                url.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_owned()
+            })
+    })
+}
+
+// GET a document, revalidating any cached copy with If-None-Match/If-Modified-Since.
+// 304 responses don't count against GitHub's rate limit.
+fn get_cached(url: &str) -> Result<CacheEntry> {
+    let cached = cache::load(url);
+
+    let mut request_builder = get_client().get(url).header("User-Agent", USER_AGENT);
+    if let Some(entry) = &cached {
+        if let Some(etag) = &entry.etag {
+            request_builder = request_builder.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &entry.last_modified {
+            request_builder = request_builder.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = request_builder.send()?;
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        if let Some(entry) = cached {
+            println!("Not modified, using cached response for {url}");
+            return Ok(entry);
+        }
+    }
 
     if !response.status().is_success() {
         return Err(format!("GitHub API request failed: {}", response.status()).into());
     }
 
-    Ok(response.json()?)
+    let header_string = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(ToOwned::to_owned)
+    };
+    let etag = header_string(header::ETAG);
+    let last_modified = header_string(header::LAST_MODIFIED);
+    let link = header_string(header::LINK);
+
+    let entry = CacheEntry {
+        url: url.to_owned(),
+        etag,
+        last_modified,
+        link,
+        body: response.text()?,
+    };
+
+    if let Err(err) = cache::store(&entry) {
+        println!("Failed to cache response for {url}: {err}");
+    }
+
+    Ok(entry)
 }
 
-pub fn fetch_releases(repo: &str) -> Result<Vec<Release>> {
-    let response = get_client()
-        .get(format!(
-            "https://api.github.com/repos/{repo}/releases?per_page=100"
-        ))
-        .header("User-Agent", USER_AGENT)
-        .send()?;
+fn get_json_cached<T: DeserializeOwned>(url: &str) -> Result<T> {
+    Ok(serde_json::from_str(&get_cached(url)?.body)?)
+}
 
-    if !response.status().is_success() {
-        return Err(format!("GitHub API request failed: {}", response.status()).into());
+fn page_from_entry<T: DeserializeOwned>(entry: &CacheEntry) -> Result<Page<T>> {
+    Ok(Page {
+        items: serde_json::from_str(&entry.body)?,
+        next: entry.link.as_deref().and_then(parse_next_link),
+    })
+}
+
+// Fetch one page of a listing; `url` is either a first page URL or a `Page::next`
+pub fn fetch_page<T: DeserializeOwned>(url: &str) -> Result<Page<T>> {
+    page_from_entry(&get_cached(url)?)
+}
+
+// Cached copy of a page, without touching the network
+pub fn cached_page<T: DeserializeOwned>(url: &str) -> Option<Page<T>> {
+    page_from_entry(&cache::load(url)?).ok()
+}
+
+fn latest_release_url(repo: &str) -> String {
+    format!("https://api.github.com/repos/{repo}/releases/latest")
+}
+
+fn releases_url(repo: &str) -> String {
+    format!("https://api.github.com/repos/{repo}/releases?per_page=100")
+}
+
+pub fn fetch_latest_release(repo: &str) -> Result<Release> {
+    get_json_cached(&latest_release_url(repo))
+}
+
+// First page of releases, newest first
+pub fn fetch_releases(repo: &str) -> Result<Page<Release>> {
+    fetch_page(&releases_url(repo))
+}
+
+pub fn cached_releases(repo: &str) -> Option<Page<Release>> {
+    cached_page(&releases_url(repo))
+}
+
+// Resolve a tag to its commit through the git refs API. `get` performs the
+// requests, so the same logic works against the network and the cache.
+fn lookup_tag(repo: &str, name: &str, get: impl Fn(&str) -> Result<CacheEntry>) -> Result<Tag> {
+    let body = get(&format!(
+        "https://api.github.com/repos/{repo}/git/ref/tags/{name}"
+    ))?
+    .body;
+    let mut object = serde_json::from_str::<GitRef>(&body)?.object;
+
+    // Annotated tags point to a tag object, which in turn points to the commit
+    while object.kind == "tag" {
+        let body = get(&format!(
+            "https://api.github.com/repos/{repo}/git/tags/{}",
+            object.sha
+        ))?
+        .body;
+        object = serde_json::from_str::<GitTag>(&body)?.object;
+    }
+
+    if object.kind != "commit" {
+        return Err(format!("Tag {name} points to a {}, not a commit", object.kind).into());
     }
 
-    Ok(response.json()?)
+    Ok(Tag {
+        name: name.to_owned(),
+        commit: Commit { sha: object.sha },
+    })
+}
+
+// Look up a single tag by name. Tags don't move, so a cached answer is used as is.
+pub fn fetch_tag(repo: &str, name: &str) -> Result<Tag> {
+    lookup_tag(repo, name, |url| {
+        cache::load(url).map_or_else(|| get_cached(url), Ok)
+    })
 }
 
-pub fn fetch_tags(repo: &str) -> Result<Vec<Tag>> {
+pub fn cached_tag(repo: &str, name: &str) -> Option<Tag> {
+    lookup_tag(repo, name, |url| {
+        cache::load(url).ok_or_else(|| format!("{url} is not cached").into())
+    })
+    .ok()
+}
+
+// The optional `manifest.json` asset a release can carry
+pub fn fetch_release_manifest(release: &Release) -> Result<Option<ReleaseManifest>> {
+    let Some(asset) = release
+        .assets
+        .iter()
+        .find(|a| a.name == MANIFEST_ASSET_NAME)
+    else {
+        return Ok(None);
+    };
+
+    let bytes = download(&asset.url, |_| {}, &CancellationToken::default())?;
+    Ok(Some(serde_json::from_slice(&bytes)?))
+}
+
+// Response to a request for part of a file
+pub enum RangeFetch {
+    Partial {
+        bytes: Bytes,
+        // Offset of `bytes` in the file, and the file's size
+        start: u64,
+        total: u64,
+    },
+    // The server ignored the Range header and sent the whole file
+    Full(Bytes),
+}
+
+// "bytes 100-199/12345" -> (100, 12345)
+fn parse_content_range(content_range: &str) -> Option<(u64, u64)> {
+    let (range, total) = content_range.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _) = range.split_once('-')?;
+    Some((start.parse().ok()?, total.parse().ok()?))
+}
+
+// GET part of a file, `range` being an HTTP byte range such as "0-99" or "-100"
+// (the last 100 bytes)
+pub fn fetch_range(url: &str, range: &str) -> Result<RangeFetch> {
     let response = get_client()
-        .get(format!(
-            "https://api.github.com/repos/{repo}/tags?per_page=100"
-        ))
+        .get(url)
+        .header("Accept", "application/octet-stream")
         .header("User-Agent", USER_AGENT)
+        .header(header::RANGE, format!("bytes={range}"))
         .send()?;
 
-    if !response.status().is_success() {
-        return Err(format!("GitHub API request failed: {}", response.status()).into());
+    match response.status() {
+        StatusCode::PARTIAL_CONTENT => {
+            let (start, total) = response
+                .headers()
+                .get(header::CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_content_range)
+                .ok_or("Invalid Content-Range header")?;
+            Ok(RangeFetch::Partial {
+                bytes: response.bytes()?,
+                start,
+                total,
+            })
+        }
+        status if status.is_success() => Ok(RangeFetch::Full(response.bytes()?)),
+        status => Err(format!("Request for {url} failed: {status}").into()),
     }
+}
 
-    let tags: Vec<Tag> = response.json()?;
+// Smoothed transfer rate, so the speed and ETA don't jump around with every chunk
+struct TransferMeter {
+    started: Instant,
+    last_sample: Instant,
+    last_sample_bytes: u64,
+    bytes_per_second: f64,
+}
+
+impl TransferMeter {
+    const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+    // Weight of the newest sample in the moving average
+    const SMOOTHING: f64 = 0.3;
 
-    Ok(tags.clone())
+    fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            last_sample: now,
+            last_sample_bytes: 0,
+            bytes_per_second: 0.0,
+        }
+    }
+
+    fn progress(&mut self, done: u64, total: Option<u64>) -> TransferProgress {
+        let elapsed = self.last_sample.elapsed();
+        if elapsed >= Self::SAMPLE_INTERVAL {
+            let rate = (done - self.last_sample_bytes) as f64 / elapsed.as_secs_f64();
+            self.bytes_per_second = if self.bytes_per_second > 0.0 {
+                Self::SMOOTHING * rate + (1.0 - Self::SMOOTHING) * self.bytes_per_second
+            } else {
+                rate
+            };
+            self.last_sample = Instant::now();
+            self.last_sample_bytes = done;
+        }
+
+        TransferProgress {
+            done,
+            total,
+            bytes_per_second: self.bytes_per_second,
+        }
+    }
 }
 
-pub fn download<U: IntoUrl>(url: U, progress_cb: impl Fn(f32)) -> Result<Bytes> {
+pub fn download<U: IntoUrl>(
+    url: U,
+    progress_cb: impl Fn(TransferProgress),
+    cancellation: &CancellationToken,
+) -> Result<Bytes> {
     let request_builder = get_client()
         .get(url)
         .header("Accept", "application/octet-stream")
         .header("User-Agent", USER_AGENT);
 
-    let mut response = request_builder.send()?;
+    let mut response = request_builder.send()?.error_for_status()?;
     println!("Status: {}", response.status());
     println!("Headers: {:?}", response.headers());
 
-    let total_size = response.content_length().unwrap_or(0);
+    let total_size = response.content_length().filter(|&len| len > 0);
 
     let mut bytes = Vec::new();
     let mut downloaded: u64 = 0;
     let mut buffer = [0; 16384];
+    let mut meter = TransferMeter::new();
 
     loop {
+        cancellation.check()?;
+
         let bytes_read = response.read(&mut buffer)?;
         if bytes_read == 0 {
             break;
@@ -96,13 +345,45 @@ pub fn download<U: IntoUrl>(url: U, progress_cb: impl Fn(f32)) -> Result<Bytes>
         downloaded += bytes_read as u64;
 
         // Show progress
-        if total_size > 0 {
-            let percentage = downloaded as f64 / total_size as f64;
-            progress_cb(percentage as f32);
-        }
+        progress_cb(meter.progress(downloaded, total_size));
     }
 
-    println!("\nDownload complete!");
+    println!(
+        "\nDownload complete! {downloaded} bytes in {:.1}s",
+        meter.started.elapsed().as_secs_f64()
+    );
 
     Ok(bytes.into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_next_page_link() {
+        let link = "<https://api.github.com/repositories/1/releases?per_page=100&page=2>; rel=\"next\", \
+                    <https://api.github.com/repositories/1/releases?per_page=100&page=5>; rel=\"last\"";
+        assert_eq!(
+            parse_next_link(link).as_deref(),
+            Some("https://api.github.com/repositories/1/releases?per_page=100&page=2")
+        );
+    }
+
+    #[test]
+    fn finds_next_link_in_any_position() {
+        let link = "<https://example.com/?page=1>; rel=\"prev\",<https://example.com/?page=3>;rel=\"next\"";
+        assert_eq!(
+            parse_next_link(link).as_deref(),
+            Some("https://example.com/?page=3")
+        );
+    }
+
+    #[test]
+    fn no_next_link_on_last_page() {
+        let link = "<https://example.com/?page=1>; rel=\"first\", <https://example.com/?page=4>; rel=\"prev\"";
+        assert_eq!(parse_next_link(link), None);
+        assert_eq!(parse_next_link(""), None);
+        assert_eq!(parse_next_link("garbage"), None);
+    }
+}
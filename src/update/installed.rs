@@ -0,0 +1,107 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{atomic_write::write_atomically, Result, SDCARD_ROOT, UPDATER_DATA_DIR};
+
+// CRC32 of every file the updater installed, keyed by its path relative to the
+// SD card root. A file whose contents no longer match was modified by the user.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct InstalledFiles {
+    #[serde(default)]
+    files: BTreeMap<String, u32>,
+}
+
+fn installed_files_path() -> PathBuf {
+    PathBuf::from(SDCARD_ROOT)
+        .join(UPDATER_DATA_DIR)
+        .join("installed_files.json")
+}
+
+// Always use "/" so the keys don't depend on the platform
+fn key(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn file_crc32(path: &Path) -> std::io::Result<u32> {
+    let mut file = File::open(path)?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hasher.finalize());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+impl InstalledFiles {
+    // Without a record (e.g. before the first update by this updater) no file
+    // counts as modified
+    pub fn load() -> Self {
+        std::fs::read_to_string(installed_files_path())
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn store(&self) -> Result<()> {
+        write_atomically(
+            &installed_files_path(),
+            serde_json::to_string(self)?.as_bytes(),
+        )
+    }
+
+    // Whether the file at `relative_path` below `root` was installed by the
+    // updater and changed since
+    pub fn is_modified(&self, root: &Path, relative_path: &Path) -> bool {
+        let Some(&recorded) = self.files.get(&key(relative_path)) else {
+            return false;
+        };
+
+        match file_crc32(&root.join(relative_path)) {
+            Ok(current) => current != recorded,
+            // Deleted files are simply installed again
+            Err(_) => false,
+        }
+    }
+
+    // Installed files which are within the scope of an update but which its
+    // archive no longer contains, i.e. files dropped or renamed upstream
+    pub fn dropped_files(
+        &self,
+        planned: &[(PathBuf, u32)],
+        in_scope: impl Fn(&str) -> bool,
+    ) -> Vec<PathBuf> {
+        let planned: HashSet<String> = planned.iter().map(|(path, _)| key(path)).collect();
+
+        self.files
+            .keys()
+            .filter(|path| !planned.contains(*path) && in_scope(path))
+            .map(PathBuf::from)
+            .collect()
+    }
+
+    pub fn forget(&mut self, removed: &[PathBuf]) {
+        for relative_path in removed {
+            self.files.remove(&key(relative_path));
+        }
+    }
+
+    // Remember the hashes of freshly installed files
+    pub fn record(&mut self, written: &[(PathBuf, u32)]) {
+        for (relative_path, crc32) in written {
+            self.files.insert(key(relative_path), *crc32);
+        }
+    }
+}
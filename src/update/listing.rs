@@ -0,0 +1,181 @@
+use bytes::Bytes;
+
+use super::fetching::{fetch_range, RangeFetch};
+use crate::Result;
+
+// Size of the end of central directory record without its comment, which can
+// be up to 64 KiB long
+const END_OF_CENTRAL_DIRECTORY_LEN: u64 = 22;
+const MAX_COMMENT_LEN: u64 = 0xFFFF;
+
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0606_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+
+// A file of a zip archive, as listed in its central directory
+#[derive(Clone, Debug)]
+pub struct ListedEntry {
+    pub name: String,
+    pub size: u64,
+}
+
+// Little-endian integer of `len` bytes at `at`
+fn read_le(bytes: &[u8], at: usize, len: usize) -> Result<u64> {
+    let field = bytes
+        .get(at..at + len)
+        .ok_or("Truncated zip central directory")?;
+    Ok(field
+        .iter()
+        .rev()
+        .fold(0, |value, &byte| (value << 8) | u64::from(byte)))
+}
+
+// A remote file, of which the part fetched so far is kept around
+struct RemoteFile<'a> {
+    url: &'a str,
+    fetched: Bytes,
+    fetched_start: u64,
+}
+
+impl RemoteFile<'_> {
+    fn read_at(&self, offset: u64, len: u64) -> Result<Bytes> {
+        if len == 0 {
+            return Ok(Bytes::new());
+        }
+
+        let fetched_end = self.fetched_start + self.fetched.len() as u64;
+        if offset >= self.fetched_start && offset + len <= fetched_end {
+            let start = usize::try_from(offset - self.fetched_start)?;
+            return Ok(self.fetched.slice(start..start + usize::try_from(len)?));
+        }
+
+        match fetch_range(self.url, &format!("{offset}-{}", offset + len - 1))? {
+            RangeFetch::Partial { bytes, .. } => Ok(bytes),
+            RangeFetch::Full(bytes) => {
+                let start = usize::try_from(offset)?;
+                let end = usize::try_from(offset + len)?;
+                if end > bytes.len() {
+                    return Err("Truncated zip file".into());
+                }
+                Ok(bytes.slice(start..end))
+            }
+        }
+    }
+}
+
+// Offset, size and entry count of the central directory
+fn find_central_directory(file: &RemoteFile) -> Result<(u64, u64, u64)> {
+    let tail = &file.fetched;
+    let position = (0..=tail
+        .len()
+        .saturating_sub(END_OF_CENTRAL_DIRECTORY_LEN as usize))
+        .rev()
+        .find(|&at| {
+            read_le(tail, at, 4).ok() == Some(u64::from(END_OF_CENTRAL_DIRECTORY_SIGNATURE))
+                && read_le(tail, at + 20, 2).is_ok_and(|comment_len| {
+                    at as u64 + END_OF_CENTRAL_DIRECTORY_LEN + comment_len <= tail.len() as u64
+                })
+        })
+        .ok_or("Not a zip file (no end of central directory record)")?;
+
+    let entries = read_le(tail, position + 10, 2)?;
+    let size = read_le(tail, position + 12, 4)?;
+    let offset = read_le(tail, position + 16, 4)?;
+    if offset != 0xFFFF_FFFF && size != 0xFFFF_FFFF && entries != 0xFFFF {
+        return Ok((offset, size, entries));
+    }
+
+    // Zip64 archive, the real values are in the zip64 end of central directory record
+    let locator = position
+        .checked_sub(20)
+        .filter(|&at| read_le(tail, at, 4).ok() == Some(u64::from(ZIP64_LOCATOR_SIGNATURE)))
+        .ok_or("Missing zip64 end of central directory locator")?;
+    let record_offset = read_le(tail, locator + 8, 8)?;
+    let record = file.read_at(record_offset, 56)?;
+    if read_le(&record, 0, 4)? != u64::from(ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE) {
+        return Err("Invalid zip64 end of central directory record".into());
+    }
+
+    Ok((
+        read_le(&record, 48, 8)?,
+        read_le(&record, 40, 8)?,
+        read_le(&record, 32, 8)?,
+    ))
+}
+
+fn parse_central_directory(directory: &[u8], entries: u64) -> Result<Vec<ListedEntry>> {
+    let mut listed = vec![];
+    let mut at = 0;
+    for _ in 0..entries {
+        if read_le(directory, at, 4)? != u64::from(CENTRAL_HEADER_SIGNATURE) {
+            return Err("Invalid zip central directory header".into());
+        }
+
+        let mut size = read_le(directory, at + 24, 4)?;
+        let name_len = usize::try_from(read_le(directory, at + 28, 2)?)?;
+        let extra_len = usize::try_from(read_le(directory, at + 30, 2)?)?;
+        let comment_len = usize::try_from(read_le(directory, at + 32, 2)?)?;
+
+        let name_start = at + 46;
+        let name = directory
+            .get(name_start..name_start + name_len)
+            .ok_or("Truncated zip central directory")?;
+
+        // The zip64 extra field holds the real size, if it doesn't fit 32 bits
+        if size == 0xFFFF_FFFF {
+            let extra = &directory[name_start + name_len..];
+            let mut field = 0;
+            while field + 4 <= extra_len {
+                let id = read_le(extra, field, 2)?;
+                let field_len = usize::try_from(read_le(extra, field + 2, 2)?)?;
+                if id == 0x0001 {
+                    size = read_le(extra, field + 4, 8)?;
+                    break;
+                }
+                field += 4 + field_len;
+            }
+        }
+
+        listed.push(ListedEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            size,
+        });
+        at = name_start + name_len + extra_len + comment_len;
+    }
+
+    Ok(listed)
+}
+
+// List the files of a remote zip archive. Only its central directory is fetched
+// when the server supports range requests.
+pub fn list_remote_zip(url: &str) -> Result<Vec<ListedEntry>> {
+    let file = match fetch_range(
+        url,
+        &format!("-{}", END_OF_CENTRAL_DIRECTORY_LEN + MAX_COMMENT_LEN),
+    )? {
+        RangeFetch::Partial { bytes, start, .. } => RemoteFile {
+            url,
+            fetched: bytes,
+            fetched_start: start,
+        },
+        RangeFetch::Full(bytes) => {
+            println!("Range requests unsupported, downloaded {url} in full");
+            RemoteFile {
+                url,
+                fetched: bytes,
+                fetched_start: 0,
+            }
+        }
+    };
+
+    let (offset, size, entries) = find_central_directory(&file)?;
+    let directory = file.read_at(offset, size)?;
+    let listed = parse_central_directory(&directory, entries)?;
+
+    // Folders are implied by the file paths
+    Ok(listed
+        .into_iter()
+        .filter(|entry| !entry.name.ends_with('/'))
+        .collect())
+}
@@ -1,221 +1,541 @@
 use crate::{
-    app_state::{AppStateManager, Progress},
-    github::ReleaseAndTag,
-    Result, SDCARD_ROOT,
+    app_state::{
+        AppStateManager, CancellationToken, Cancelled, Confirmation, InstallPlan, Phase, Progress,
+        Submenu,
+    },
+    battery,
+    device::supported_devices,
+    file_picker::FilePicker,
+    github::{Asset, Commit, Release, ReleaseAndTag, Tag},
+    network,
+    platform::Platform,
+    profiles::{PostAction, Profile},
+    reboot::RebootStrategy,
+    settings::{Channel, ModifiedFilesPolicy, SettingsField},
+    version::{sort_releases, InstalledVersion},
+    Result, SDCARD_ROOT, UPDATER_DATA_DIR,
 };
 use bytes::Bytes;
-use fetching::{download, fetch_latest_release, fetch_releases, fetch_tags};
-use regex::Regex;
+use extract::{extract_zip, planned_files, verify_zip, ExtractPolicy, ExtractReport, ExtractStage};
+use fetching::{
+    cached_page, cached_releases, cached_tag, download, fetch_page, fetch_release_manifest,
+    fetch_releases, fetch_tag, Page,
+};
+use installed::InstalledFiles;
+use listing::list_remote_zip;
+use sha2::{Digest, Sha256};
 
 use std::{
-    fs::File,
-    io::{Cursor, Read, Write},
-    path::PathBuf,
-    process::exit,
+    collections::HashSet,
+    path::{Path, PathBuf},
     thread,
 };
 
+mod cache;
+mod extract;
 mod fetching;
+mod installed;
+mod listing;
+mod self_update;
+mod staged;
 
-fn extract_zip<T: Fn(&str) -> bool>(
-    bytes: Bytes,
-    filter: T,
-    progress_cb: impl Fn(f32),
-) -> Result<()> {
-    pub fn file_write_all_bytes(path: &PathBuf, bytes: &[u8]) -> Result<usize> {
-        let mut file = File::create(path)?;
-        file.set_len(0)?;
-        Ok(file.write(bytes)?)
-    }
-
-    // Extract the update package
-    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
-    let target_directory = PathBuf::from(SDCARD_ROOT);
-    let archive_len = archive.len();
-
-    for file_number in 0..archive_len {
-        let mut next = archive.by_index(file_number)?;
-
-        let sanitized_name = next.mangled_name();
+pub use fetching::set_tls_policy;
+pub use self_update::confirm_self_update;
+use self_update::self_update;
+pub use staged::StagedUpdate;
 
-        if !filter(sanitized_name.as_os_str().to_string_lossy().as_ref()) {
-            println!("Skipping file: {}", sanitized_name.display());
-            continue;
-        }
+// Repository NextUI releases are fetched from
+fn nextui_repo(app_state: &AppStateManager) -> String {
+    app_state.settings().source_repo
+}
 
-        if next.is_dir() {
-            let extracted_folder_path = target_directory.join(sanitized_name);
-            std::fs::create_dir_all(&extracted_folder_path)?;
-            println!("Created directory: {}", extracted_folder_path.display());
-        } else if next.is_file() {
-            let mut buffer: Vec<u8> = Vec::new();
-            let _bytes_read = next.read_to_end(&mut buffer)?;
-            let extracted_file_path = target_directory.join(sanitized_name);
-            file_write_all_bytes(&extracted_file_path, buffer.as_ref())?;
-            println!("Extracted file: {}", extracted_file_path.display());
-        }
+// Whether `release` is offered on the chosen channel
+fn on_channel(app_state: &AppStateManager, release: &Release) -> bool {
+    !release.prerelease || app_state.settings().channel == Channel::Prerelease
+}
 
-        progress_cb(file_number as f32 / (archive_len - 1) as f32);
+// Add the following pages to `page` while none of its releases is on the
+// selected channel, e.g. when the newest page only holds pre-releases
+fn extend_until_on_channel(
+    app_state: &AppStateManager,
+    page: &mut Page<Release>,
+    fetch: impl Fn(&str) -> Result<Page<Release>>,
+) -> Result<()> {
+    while !page
+        .items
+        .iter()
+        .any(|release| on_channel(app_state, release))
+    {
+        let Some(next) = page.next.clone() else {
+            break;
+        };
+        println!("No releases on the selected channel yet, fetching {next}");
+        let more = fetch(&next)?;
+        page.items.extend(more.items);
+        page.next = more.next;
     }
-
     Ok(())
 }
 
-pub fn self_update(app_state: &AppStateManager) -> Result<()> {
-    // Fetch latest release information
-    app_state.start_operation("Fetching latest updater release...");
+fn is_commit_sha(s: &str) -> bool {
+    s.len() == 40 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// Resolve the commit a release was built from, without listing all tags: the
+// release's target_commitish if it is a SHA, then the git refs API, then the
+// release's manifest. With `offline` only cached answers are used.
+fn resolve_release_commit(repo: &str, release: &Release, offline: bool) -> Option<Tag> {
+    let tag = |sha: String| Tag {
+        name: release.tag_name.clone(),
+        commit: Commit { sha },
+    };
 
-    println!("Fetching latest updater release...");
+    if is_commit_sha(&release.target_commitish) {
+        return Some(tag(release.target_commitish.clone()));
+    }
 
-    let release = fetch_latest_release("adam-ikari/nextui-updater-pak")?;
+    if offline {
+        return cached_tag(repo, &release.tag_name);
+    }
 
-    println!("Latest updater release: {release:?}");
+    match fetch_tag(repo, &release.tag_name) {
+        Ok(resolved) => return Some(resolved),
+        Err(err) => println!("Looking up tag {} failed: {err}", release.tag_name),
+    }
 
-    let available = semver::Version::parse(&release.tag_name)?;
-    let installed = semver::Version::parse(env!("CARGO_PKG_VERSION"))?;
+    match fetch_release_manifest(release) {
+        Ok(manifest) => manifest.and_then(|m| m.commit).map(tag),
+        Err(err) => {
+            println!("Fetching manifest of {} failed: {err}", release.tag_name);
+            None
+        }
+    }
+}
 
-    if available > installed {
-        println!("New version available: {available} (current: {installed})");
-        app_state.set_current_operation(Some("Downloading updater...".to_string()));
-    } else {
-        println!("No updates available");
-        return Ok(());
+// Publish the first page of releases to the app state, merged into the releases
+// already listed so pages loaded since stay loaded. Only the latest release is
+// resolved over the network, the others are resolved when selected.
+fn apply_nextui_releases(
+    app_state: &AppStateManager,
+    latest_releases: &Page<Release>,
+    offline: bool,
+) -> std::result::Result<(), String> {
+    if latest_releases.items.is_empty() {
+        // Connected, but no results
+        return Err("Fetching releases returned 0 releases".to_string());
     }
 
-    let asset = release
-        .assets
+    // Build ReleaseAndTag list for app state, newest version first
+    let mut releases_and_tags: Vec<ReleaseAndTag> = latest_releases
+        .items
         .iter()
-        .find(|a| a.name.to_lowercase().ends_with(".pakz"))
-        .ok_or("No .pakz asset found")?;
-
-    let bytes = download(&asset.url, |pr| {
-        app_state.update_progress(pr);
-    })?;
+        .filter(|release| on_channel(app_state, release))
+        .map(|release| ReleaseAndTag {
+            release: release.clone(),
+            tag: None,
+        })
+        .collect();
+    if releases_and_tags.is_empty() {
+        return Err(
+            "No stable releases found, pre-releases can be enabled in Settings".to_string(),
+        );
+    }
+    let fresh: HashSet<String> = releases_and_tags
+        .iter()
+        .map(|r| r.release.tag_name.clone())
+        .collect();
+    let previous = app_state.nextui_releases_and_tags();
+    if let Some(previous) = &previous {
+        releases_and_tags.extend(
+            previous
+                .iter()
+                .filter(|r| !fresh.contains(&r.release.tag_name))
+                .cloned(),
+        );
+    }
+    sort_releases(&mut releases_and_tags);
+    let repo = nextui_repo(app_state);
+    for (index, entry) in releases_and_tags.iter_mut().enumerate() {
+        if fresh.contains(&entry.release.tag_name) {
+            entry.tag = resolve_release_commit(&repo, &entry.release, offline || index > 0);
+        }
+    }
+    if releases_and_tags[0].tag.is_none() {
+        println!(
+            "Latest release has no resolvable commit: {:?}",
+            releases_and_tags[0].release.tag_name
+        );
+    }
 
-    app_state
-        .set_current_operation(format!("Extracting NextUI Updater {}...", release.tag_name).into());
-    app_state.set_progress(Some(Progress::Indeterminate));
+    let current_version = app_state.current_version();
+    let current_tag_index = releases_and_tags.iter().position(|r| {
+        r.tag.as_ref().is_some_and(|tag| {
+            current_version
+                .as_ref()
+                .is_some_and(|v| v.is_commit(&tag.commit.sha))
+        })
+    });
 
-    // Move the current binary to a backup location
-    let current_binary = std::env::current_exe()?;
-    std::fs::rename(&current_binary, current_binary.with_extension("bak"))?;
+    // Keep the selector on the same release if the list is refreshed while it's open
+    let selected_index = app_state
+        .release_selection_menu()
+        .then(|| selected_release_index(app_state, &releases_and_tags))
+        .flatten();
 
-    // Extract the update package
-    let result = extract_zip(
-        bytes,
-        |_| true,
-        |pr| {
-            app_state.update_progress(pr);
-        },
-    );
+    // Save collected values to app state
+    app_state.set_nextui_release(Some(releases_and_tags[0].release.clone()));
+    app_state.set_nextui_tag(releases_and_tags[0].tag.clone());
+    app_state.set_nextui_releases_and_tags(Some(releases_and_tags));
+    // Once more pages are loaded, carry on from the last of them
+    if previous.is_none() {
+        app_state.set_nextui_releases_next_page(latest_releases.next.clone());
+    }
+    // set release selector starting index
+    app_state.set_nextui_releases_and_tags_index(Some(
+        selected_index.or(current_tag_index).unwrap_or(0),
+    ));
 
-    println!("Extraction complete!");
-    app_state.set_progress(Some(Progress::Indeterminate));
+    Ok(())
+}
 
-    if result.is_err() {
-        // Move the backup back
-        std::fs::rename(current_binary.with_extension("bak"), current_binary)?;
+// Where the release currently picked in the selector ended up in `releases_and_tags`
+fn selected_release_index(
+    app_state: &AppStateManager,
+    releases_and_tags: &[ReleaseAndTag],
+) -> Option<usize> {
+    let index = app_state.nextui_releases_and_tags_index()?;
+    let previous = app_state.nextui_releases_and_tags()?;
+    let tag_name = &previous.get(index)?.release.tag_name;
+    releases_and_tags
+        .iter()
+        .position(|r| &r.release.tag_name == tag_name)
+}
 
-        return Err("Failed to extract update package".into());
+// Show the release list from the on-disk cache, so the UI is usable straight away
+// (and while offline). Returns whether a cached list was found.
+pub fn load_cached_nextui_releases(app_state: &AppStateManager) -> bool {
+    let Some(mut releases) = cached_releases(&nextui_repo(app_state)) else {
+        return false;
+    };
+    let cached = |url: &str| cached_page(url).ok_or_else(|| format!("{url} is not cached").into());
+    if let Err(err) = extend_until_on_channel(app_state, &mut releases, cached) {
+        println!("Following cached releases stopped: {err}");
     }
 
-    app_state.set_current_operation(Some(
-        "Self-update success! Restarting updater...".to_string(),
-    ));
-
-    // Give the user a moment to see the completion message
-    thread::sleep(std::time::Duration::from_secs(1));
-
-    // "5" is the exit code for "restart required"
-    exit(5);
+    match apply_nextui_releases(app_state, &releases, true) {
+        Ok(()) => {
+            println!("Loaded {} cached releases", releases.items.len());
+            true
+        }
+        Err(err) => {
+            println!("Ignoring cached releases: {err}");
+            false
+        }
+    }
 }
 
 pub fn do_nextui_release_check(app_state: &AppStateManager) {
-    // Fetch latest release information
-    app_state.start_operation("Fetching latest NextUI release...");
-    let repo = "adam-ikari/NextUI-CN";
+    // With a cached list on screen, revalidate quietly in the background
+    let have_cached = app_state.nextui_releases_and_tags().is_some();
+    let fail = |message: String| {
+        println!("{message}");
+        if have_cached {
+            app_state.finish_operation();
+            app_state.set_error(Some(format!(
+                "Showing cached releases, refresh failed:\n{message}"
+            )));
+        } else {
+            app_state.set_operation_failed(&message);
+        }
+    };
 
     // Fetch latest releases information
-    app_state.start_operation("Fetching latest NextUI releases...");
-    let latest_releases = match fetch_releases(repo) {
+    if !have_cached {
+        app_state.start_operation("Fetching latest NextUI releases...");
+    }
+    let latest_releases = fetch_releases(&nextui_repo(app_state)).and_then(|mut releases| {
+        extend_until_on_channel(app_state, &mut releases, fetch_page)?;
+        Ok(releases)
+    });
+    let latest_releases = match latest_releases {
         Ok(releases) => releases,
         Err(err) => {
             // Failed connection
             println!("Fetching releases failed: {:?}", err.source());
-            app_state.set_operation_failed(&format!("Fetching releases failed: {err}"));
+            fail(format!("Fetching releases failed: {err}"));
             return;
         }
     };
-    if latest_releases.is_empty() {
-        // Connected, but no results
-        println!("Fetching releases returned 0 releases");
-        app_state.set_operation_failed("Fetching releases returned 0 releases");
+
+    if let Err(err) = apply_nextui_releases(app_state, &latest_releases, false) {
+        fail(err);
         return;
     }
 
-    // Fetch latest tag information
-    app_state.start_operation("Fetching latest NextUI tags...");
-    let mut latest_tags = match fetch_tags(repo) {
-        Ok(tags) => tags,
-        Err(err) => {
-            // Failed connection
-            println!("Fetching tags failed: {:?}", err.source());
-            app_state.set_operation_failed(&format!("Fetching tags failed: {err}"));
-            return;
+    if !have_cached {
+        app_state.finish_operation();
+    }
+}
+
+// The newest NextUI archive copied to the root of the SD card, for installing
+// without a connection
+fn find_sd_card_archive(platform: Option<Platform>) -> Option<PathBuf> {
+    std::fs::read_dir(SDCARD_ROOT)
+        .ok()?
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_lowercase();
+            let is_zip = Path::new(&name)
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+            is_zip
+                && name.starts_with("nextui")
+                && !name.contains("updater")
+                && !platform.is_some_and(|p| p.is_foreign_asset(&name))
+        })
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+// Check GitHub can be reached before talking to it, showing the offline screen
+// rather than a network error if it can't. Cached releases stay on screen, with
+// the offline screen a button away.
+pub fn check_connection(app_state: &AppStateManager) -> bool {
+    app_state.start_operation("Checking connection...");
+    let status = network::check(app_state.settings().tls);
+    app_state.set_network(Some(status));
+    app_state.finish_operation();
+
+    if !status.is_online() {
+        let archive = find_sd_card_archive(app_state.platform());
+        if app_state.nextui_releases_and_tags().is_some() {
+            app_state.set_sd_card_archive(archive);
+        } else {
+            app_state.show_offline(archive);
         }
-    };
-    if latest_tags.is_empty() {
-        // Connected, but no results
-        println!("Fetching tags returned 0 tags");
-        app_state.set_operation_failed("Fetching tags returned 0 tags");
+    }
+    status.is_online()
+}
+
+// Check the connection again from the offline screen, in the background
+pub fn retry_connection(app_state: &'static AppStateManager) {
+    app_state.set_error(None);
+
+    thread::spawn(move || {
+        if check_connection(app_state) {
+            reconnected(app_state);
+        }
+    });
+}
+
+// Back online after a failed check: leave the offline screen and fetch the
+// releases again, unless something else is going on
+pub fn reconnected(app_state: &AppStateManager) {
+    app_state.clear_unresolved_releases();
+    if matches!(app_state.submenu(), Submenu::Offline) {
+        app_state.leave_offline();
+    }
+    if app_state.current_operation().is_none() {
+        do_nextui_release_check(app_state);
+    }
+}
+
+// Fetch the next page of releases for the version selector, in the background
+pub fn load_more_nextui_releases(app_state: &'static AppStateManager) {
+    if app_state.loading_more_releases() {
         return;
     }
+    let Some(next_page) = app_state.nextui_releases_next_page() else {
+        return;
+    };
+    app_state.set_loading_more_releases(true);
 
-    // Build ReleaseAndTag list for app state
-    let mut releases_and_tags: Vec<ReleaseAndTag> = vec![];
-    let mut check_latest_release = true;
-    let current_tag = app_state.current_version().unwrap_or_default();
-    let mut current_tag_found = false;
-    for release in &latest_releases {
-        if let Some(tag_index) = latest_tags
-            .iter()
-            .position(|tag| tag.name == release.tag_name)
-        {
-            releases_and_tags.push(ReleaseAndTag {
-                release: (release.clone()),
-                tag: (latest_tags[tag_index].clone()),
-            });
-            if latest_tags[tag_index].commit.sha.starts_with(&current_tag) {
-                // set release selector starting index
-                app_state.set_nextui_releases_and_tags_index(Some(releases_and_tags.len() - 1));
-                current_tag_found = true;
+    thread::spawn(move || {
+        println!("Fetching more releases from {next_page}");
+        match fetch_page::<Release>(&next_page) {
+            Ok(page) => {
+                let repo = nextui_repo(app_state);
+                let more = page
+                    .items
+                    .iter()
+                    .filter(|release| on_channel(app_state, release))
+                    .map(|release| ReleaseAndTag {
+                        release: release.clone(),
+                        tag: resolve_release_commit(&repo, release, true),
+                    });
+                let mut releases_and_tags =
+                    app_state.nextui_releases_and_tags().unwrap_or_default();
+                releases_and_tags.extend(more);
+                sort_releases(&mut releases_and_tags);
+                let selected_index = selected_release_index(app_state, &releases_and_tags);
+                app_state.set_nextui_releases_and_tags(Some(releases_and_tags));
+                app_state.set_nextui_releases_and_tags_index(selected_index.or(Some(0)));
+                app_state.set_nextui_releases_next_page(page.next);
             }
-            latest_tags.remove(tag_index);
-            if check_latest_release {
-                check_latest_release = false;
+            Err(err) => {
+                println!("Fetching more releases failed: {:?}", err.source());
+                app_state.set_error(Some(format!("Fetching more releases failed: {err}")));
             }
-            continue;
         }
-        if check_latest_release {
-            // Failed to find a match for the first release
-            println!("Latest release has no matching tag: {:?}", release.tag_name);
-            app_state.set_operation_failed(&format!(
-                "Latest release has no matching tag: {:?}",
-                release.tag_name
-            ));
-            return;
+        app_state.set_loading_more_releases(false);
+    });
+}
+
+// The release picked in the version selector, or else the latest release
+fn selected_release(app_state: &AppStateManager) -> Option<Release> {
+    if app_state.release_selection_menu() {
+        let index = app_state.nextui_releases_and_tags_index().unwrap_or(0);
+        return app_state
+            .nextui_releases_and_tags()
+            .and_then(|list| list.get(index).map(|r| r.release.clone()));
+    }
+
+    app_state.nextui_release()
+}
+
+// List the contents of the selected release in the file picker, in the background
+pub fn open_file_picker(app_state: &'static AppStateManager) {
+    app_state.set_error(None);
+
+    thread::spawn(move || {
+        let result = (|| -> Result<FilePicker> {
+            let release = selected_release(app_state).ok_or("No release found")?;
+            let platform = app_state.platform();
+            // The largest archive is the one containing everything (e.g. the "all" package)
+            let asset = release
+                .assets
+                .iter()
+                .filter(|a| a.name.to_lowercase().ends_with(".zip"))
+                .filter(|a| !platform.is_some_and(|p| p.is_foreign_asset(&a.name)))
+                .max_by_key(|a| a.size)
+                .ok_or("No assets found")?;
+
+            app_state.start_operation(&format!("Reading contents of {}...", asset.name));
+            let files = list_remote_zip(&asset.url)?
+                .into_iter()
+                .filter_map(|entry| {
+                    // Named as the extraction filter sees them
+                    let path = ExtractPolicy::entry_path(&entry.name).ok()?;
+                    Some((path.to_string_lossy().into_owned(), entry.size))
+                })
+                .filter(|(name, _)| !platform.is_some_and(|p| p.is_foreign_path(name)))
+                .collect();
+
+            // Files in user data locations the release may not write can't be picked
+            let policy = ExtractPolicy {
+                allowed_protected: fetch_release_manifest(&release)
+                    .unwrap_or_else(|err| {
+                        println!("Fetching manifest of {} failed: {err}", release.tag_name);
+                        None
+                    })
+                    .unwrap_or_default()
+                    .allow_protected,
+                ..ExtractPolicy::default()
+            };
+            Ok(FilePicker::new(asset.name.clone(), files, |path| {
+                !policy.allows(Path::new(path))
+            }))
+        })();
+
+        match result {
+            Ok(picker) => {
+                app_state.finish_operation();
+                app_state.open_file_picker(picker);
+            }
+            Err(err) => {
+                println!("Listing release contents failed: {err}");
+                app_state.set_operation_failed(&format!("Failed to list files: {err}"));
+            }
         }
+    });
+}
+
+// Install only the files ticked in the file picker, staying in the updater afterwards
+pub fn install_selected_files(app_state: &'static AppStateManager) {
+    let Some(picker) = app_state.file_picker() else {
+        return;
+    };
+    app_state.close_file_picker();
+
+    let profile = Profile {
+        name: "Selected files".to_string(),
+        description: String::new(),
+        assets: vec![picker.asset.clone()],
+        include: vec![],
+        exclude: vec![],
+        files: picker.selected(),
+        keep_existing_roms: false,
+        post_actions: vec![],
+    };
+
+    app_state.set_error(None);
+    do_update(app_state, profile, false);
+}
+
+// Resolve the commit of the release picked in the version selector, in the background
+pub fn resolve_selected_nextui_release(app_state: &'static AppStateManager) {
+    let index = app_state.nextui_releases_and_tags_index().unwrap_or(0);
+    let Some(selected) = app_state
+        .nextui_releases_and_tags()
+        .and_then(|list| list.get(index).cloned())
+    else {
+        return;
+    };
+    if selected.tag.is_some()
+        || app_state.resolving_release()
+        || app_state.release_unresolved(&selected.release.tag_name)
+    {
+        return;
     }
+    app_state.set_resolving_release(true);
 
-    // Save collected values to app state
-    app_state.set_nextui_release(Some(releases_and_tags[0].release.clone()));
-    app_state.set_nextui_tag(Some(releases_and_tags[0].tag.clone()));
-    app_state.set_nextui_releases_and_tags(Some(releases_and_tags));
-    if !current_tag_found {
-        app_state.set_nextui_releases_and_tags_index(Some(0_usize));
+    thread::spawn(move || {
+        let tag = resolve_release_commit(&nextui_repo(app_state), &selected.release, false);
+        if tag.is_none() {
+            app_state.mark_release_unresolved(&selected.release.tag_name);
+        }
+        if let Some(mut releases_and_tags) = app_state.nextui_releases_and_tags() {
+            if let Some(entry) = releases_and_tags
+                .iter_mut()
+                .find(|r| r.release.tag_name == selected.release.tag_name)
+            {
+                entry.tag = tag;
+            }
+            app_state.set_nextui_releases_and_tags(Some(releases_and_tags));
+        }
+        app_state.set_resolving_release(false);
+
+        // The selection may have moved on while resolving
+        let now_selected = app_state.nextui_releases_and_tags().and_then(|list| {
+            let index = app_state.nextui_releases_and_tags_index()?;
+            list.get(index).map(|r| r.release.tag_name.clone())
+        });
+        if now_selected.is_some_and(|tag_name| tag_name != selected.release.tag_name) {
+            resolve_selected_nextui_release(app_state);
+        }
+    });
+}
+
+// Change a setting from the settings screen, save it and apply it straight away
+pub fn change_setting(app_state: &'static AppStateManager, field: SettingsField, direction: i32) {
+    let mut settings = app_state.settings();
+    settings.adjust(field, direction);
+    if let Err(err) = settings.save() {
+        println!("Failed to save settings: {err}");
+        app_state.set_error(Some(format!("Failed to save settings: {err}")));
     }
+    set_tls_policy(settings.tls);
+    app_state.set_settings(settings);
 
-    app_state.finish_operation();
+    // Another source or channel offers other releases
+    if matches!(field, SettingsField::SourceRepo | SettingsField::Channel) {
+        app_state.clear_nextui_releases();
+        thread::spawn(move || {
+            load_cached_nextui_releases(app_state);
+            do_nextui_release_check(app_state);
+        });
+    }
 }
 
 pub fn do_self_update(app_state: &AppStateManager) {
@@ -225,6 +545,10 @@ pub fn do_self_update(app_state: &AppStateManager) {
         Ok(()) => {
             app_state.finish_operation();
         }
+        Err(err) if err.is::<Cancelled>() => {
+            println!("Self-update cancelled");
+            app_state.finish_operation();
+        }
         Err(err) => {
             println!("Self-update failed: {:?}", err.source());
             app_state.set_operation_failed(&format!("Self-update failed: {err}"));
@@ -232,109 +556,677 @@ pub fn do_self_update(app_state: &AppStateManager) {
     }
 }
 
-pub fn do_update(app_state: &'static AppStateManager, full: bool) {
+pub fn do_update(app_state: &'static AppStateManager, profile: Profile, install_later: bool) {
+    thread::spawn(move || {
+        match update_nextui(app_state, &profile, install_later) {
+            Ok(()) => {}
+            Err(err) if err.is::<Cancelled>() => {
+                println!("Update cancelled");
+                app_state.set_operation_failed("Update cancelled");
+            }
+            Err(err) => {
+                println!("Update failed: {:?}", err.source());
+
+                app_state.set_operation_failed(&format!("Update failed: {err}"));
+
+                // Try to fetch latest release information again
+                if check_connection(app_state) {
+                    do_nextui_release_check(app_state);
+                }
+            }
+        }
+    });
+}
+
+// Ask which profile's update to install on the next boot, then stage it
+pub fn stage_update(app_state: &'static AppStateManager) {
     thread::spawn(move || {
-        if let Err(err) = update_nextui(app_state, full) {
-            println!("Update failed: {:?}", err.source());
+        let mut profiles = app_state.profiles();
+        let index = if profiles.len() == 1 {
+            0
+        } else {
+            let mut choices = vec!["Cancel".to_string()];
+            choices.extend(profiles.iter().map(|profile| profile.name.clone()));
+            match ask(
+                app_state,
+                Confirmation {
+                    message: "Which update should be installed on the next boot?".to_string(),
+                    choices,
+                },
+            ) {
+                Ok(0) | Err(_) => return,
+                Ok(choice) => choice - 1,
+            }
+        };
+        do_update(app_state, profiles.remove(index), true);
+    });
+}
+
+// Install the staged update now rather than on the next boot
+pub fn do_apply_staged_update(app_state: &'static AppStateManager) {
+    app_state.set_error(None);
 
+    thread::spawn(move || match apply_staged_update(app_state) {
+        Ok(()) => {}
+        Err(err) if err.is::<Cancelled>() => {
+            println!("Update cancelled");
+            app_state.set_operation_failed("Update cancelled");
+        }
+        Err(err) => {
+            println!("Installing staged update failed: {:?}", err.source());
             app_state.set_operation_failed(&format!("Update failed: {err}"));
+        }
+    });
+}
 
-            // Try to fetch latest release information again
-            do_nextui_release_check(app_state);
+pub fn cancel_staged_update(app_state: &AppStateManager) {
+    match StagedUpdate::discard() {
+        Ok(()) => app_state.set_staged_update(None),
+        Err(err) => {
+            println!("Discarding staged update failed: {err}");
+            app_state.set_error(Some(format!("Failed to cancel the pending update: {err}")));
+        }
+    }
+}
+
+// Install the archive found on the SD card from the offline screen
+pub fn install_from_sd_card(app_state: &'static AppStateManager) {
+    let Some(archive) = app_state.sd_card_archive() else {
+        return;
+    };
+    app_state.set_error(None);
+
+    thread::spawn(move || match install_sd_card_archive(app_state, &archive) {
+        Ok(()) => {}
+        Err(err) if err.is::<Cancelled>() => {
+            println!("Update cancelled");
+            app_state.set_operation_failed("Update cancelled");
+        }
+        Err(err) => {
+            println!(
+                "Installing {} failed: {:?}",
+                archive.display(),
+                err.source()
+            );
+            app_state.set_operation_failed(&format!("Update failed: {err}"));
         }
     });
 }
 
-pub fn update_nextui(app_state: &AppStateManager, full: bool) -> Result<()> {
-    let mut release = {
-        app_state.start_operation("Downloading update...");
+// Download `asset` from GitHub, trying the configured mirrors in turn if that fails
+fn download_asset(
+    app_state: &AppStateManager,
+    asset: &Asset,
+    cancellation: &CancellationToken,
+) -> Result<Bytes> {
+    let progress = |pr| app_state.update_transfer_progress(pr);
+    let mut result = download(&asset.url, progress, cancellation);
 
-        app_state
-            .nextui_release()
-            .clone()
-            .ok_or("No release found")?
+    // Mirrors proxy the public download URL, not the API one
+    let Some(public_url) = &asset.browser_download_url else {
+        return result;
     };
-    if app_state.release_selection_menu() {
-        let index = app_state.nextui_releases_and_tags_index().unwrap_or(0);
-        let relase_and_tag_vector = app_state.nextui_releases_and_tags().unwrap_or_default();
-        release = relase_and_tag_vector[index].release.clone();
+    for mirror in app_state.settings().mirrors {
+        match &result {
+            Err(err) if !err.is::<Cancelled>() => {
+                println!("Download failed: {err}, retrying through {mirror}");
+            }
+            _ => break,
+        }
+        result = download(format!("{mirror}{public_url}"), progress, cancellation);
     }
+    result
+}
 
-    let assets = release.assets;
-    let asset = assets
+// Check a download is complete and readable before anything on the SD card is touched
+fn verify_download(bytes: &Bytes, asset: &Asset) -> Result<()> {
+    if asset.size > 0 && bytes.len() as u64 != asset.size {
+        return Err(format!(
+            "Download of {} is incomplete ({} of {} bytes)",
+            asset.name,
+            bytes.len(),
+            asset.size
+        )
+        .into());
+    }
+
+    // GitHub publishes the SHA-256 of assets uploaded since mid 2025
+    if let Some(expected) = asset
+        .digest
+        .as_deref()
+        .and_then(|digest| digest.strip_prefix("sha256:"))
+    {
+        let actual = format!("{:x}", Sha256::digest(bytes));
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Err(format!("Checksum of {} doesn't match", asset.name).into());
+        }
+    }
+
+    verify_zip(bytes)
+}
+
+// Show `plan` and wait for the user to confirm it, choosing what happens to
+// modified files
+fn confirm_install_plan(
+    app_state: &AppStateManager,
+    plan: InstallPlan,
+    cancellation: &CancellationToken,
+) -> Result<ModifiedFilesPolicy> {
+    // Nobody is there to answer when installing at boot
+    if app_state.unattended() {
+        return Ok(plan.modified_policy.unwrap_or(ModifiedFilesPolicy::Backup));
+    }
+
+    app_state.show_install_plan(plan);
+    let choice = wait_for_answer(cancellation, || app_state.take_install_plan_choice());
+    app_state.end_install_plan();
+    choice
+}
+
+// Ask the user a question, returning the index of the chosen answer. B cancels
+// the operation.
+fn ask(app_state: &AppStateManager, confirmation: Confirmation) -> Result<usize> {
+    let cancellation = app_state.begin_cancellable();
+    app_state.ask_confirmation(confirmation);
+    let answer = wait_for_answer(&cancellation, || app_state.take_confirmation_answer());
+    app_state.end_confirmation();
+    app_state.end_cancellable();
+    answer
+}
+
+// Ask the user to confirm something, declining cancels the operation
+fn confirm_or_cancel(
+    app_state: &AppStateManager,
+    message: String,
+    accept: &str,
+    decline: &str,
+) -> Result<()> {
+    let choices = vec![decline.to_string(), accept.to_string()];
+    match ask(app_state, Confirmation { message, choices })? {
+        0 => Err(Box::new(Cancelled)),
+        _ => Ok(()),
+    }
+}
+
+// Poll for the user's answer to a prompt, until it comes or the operation is cancelled
+fn wait_for_answer<T>(
+    cancellation: &CancellationToken,
+    take_answer: impl Fn() -> Option<T>,
+) -> Result<T> {
+    loop {
+        cancellation.check()?;
+        if let Some(answer) = take_answer() {
+            return Ok(answer);
+        }
+        thread::sleep(std::time::Duration::from_millis(100));
+    }
+}
+
+fn display_paths(paths: &[PathBuf]) -> Vec<String> {
+    paths
         .iter()
-        .find(|a| a.name.contains(if full { "all" } else { "base" }))
-        .or(assets.first())
-        .ok_or("No assets found")?;
+        .map(|path| path.display().to_string())
+        .collect()
+}
+
+#[allow(clippy::too_many_lines)]
+// Download and install the selected release, or with `install_later` stage it
+// to be installed on the next boot
+pub fn update_nextui(
+    app_state: &AppStateManager,
+    profile: &Profile,
+    install_later: bool,
+) -> Result<()> {
+    let mut phases = vec![Phase::FetchMetadata, Phase::Download, Phase::Verify];
+    if !install_later {
+        phases.extend([Phase::Backup, Phase::Extract]);
+    }
+    phases.push(Phase::Finalize);
+    if profile.reboots() && !install_later {
+        phases.push(Phase::Reboot);
+    }
+    app_state.start_pipeline(&phases);
+
+    app_state.enter_phase(Phase::FetchMetadata, "Preparing update...");
+    // A staged update is checked when it is installed
+    if !install_later {
+        battery::check_for_install(app_state.settings().min_battery_percent)?;
+    }
+    let release = selected_release(app_state).ok_or("No release found")?;
+
+    // The release manifest decides which protected locations may be written
+    let manifest = fetch_release_manifest(&release)
+        .unwrap_or_else(|err| {
+            println!("Fetching manifest of {} failed: {err}", release.tag_name);
+            None
+        })
+        .unwrap_or_default();
+
+    // Warn before installing a release which doesn't support this device
+    if let Some(device) = app_state.device() {
+        if device.is_supported(&supported_devices(&release, &manifest)) == Some(false) {
+            confirm_or_cancel(
+                app_state,
+                format!(
+                    "WARNING\n\
+                    NextUI {} does not list {} as supported!\n\
+                    It may not boot or work properly on this device",
+                    release.tag_name,
+                    device.description()
+                ),
+                "Install anyway",
+                "Cancel",
+            )?;
+            app_state.enter_phase(Phase::FetchMetadata, "Preparing update...");
+        }
+    }
+
+    let policy = ExtractPolicy {
+        allowed_protected: manifest.allow_protected.clone(),
+        ..ExtractPolicy::default()
+    };
+
+    let assets = release.assets;
+    let platform = app_state.platform();
+    let asset = profile
+        .find_asset(&assets, platform)
+        .ok_or_else(|| match platform {
+            Some(platform) => format!("No assets found for {}", platform.name()),
+            None => "No assets found".to_string(),
+        })?;
 
     // Download the asset
-    app_state.start_determinate_operation(&format!("Downloading {}...", asset.name));
+    app_state.enter_phase(Phase::Download, &format!("Downloading {}...", asset.name));
     println!("Downloading from {}", asset.url);
 
-    let bytes = download(&asset.url, |pr| app_state.update_progress(pr))?;
+    let cancellation = app_state.begin_cancellable();
+    let bytes = download_asset(app_state, asset, &cancellation)?;
+    app_state.end_cancellable();
 
-    app_state.set_current_operation(format!("Extracting {}...\nPlease wait...", asset.name).into());
-    app_state.set_progress(Some(Progress::Indeterminate));
+    app_state.enter_phase(Phase::Verify, &format!("Verifying {}...", asset.name));
+    verify_download(&bytes, asset)?;
 
-    // Extract the update package
-    if full {
-        let emu_tag_re = Regex::new(r"\((?<emu>\w+)\)").expect("Failed to compile regex");
-        // Full update, extract all files, except for Roms folders which already exist
-        extract_zip(
-            bytes,
-            |file| {
-                if file.starts_with("Roms/") {
-                    // Extract the emu tag from the folder name
-                    if let Some(captures) = emu_tag_re.captures(file) {
-                        if let Some(emu) = captures.name("emu").map(|c| c.as_str()) {
-                            // Check if the emu tag already exists in the roms folder
-                            if std::fs::read_dir(PathBuf::from(SDCARD_ROOT).join("Roms"))
-                                .map(|d| {
-                                    d.filter_map(std::result::Result::ok).any(|e| {
-                                        e.file_name()
-                                            .to_string_lossy()
-                                            .contains(format!("({emu})").as_str())
-                                    })
-                                })
-                                .unwrap_or(false)
-                            {
-                                println!("Roms folder for {emu} already exists, skipping");
-                                return false;
-                            }
-                        }
-                    }
-                }
+    if install_later {
+        app_state.enter_phase(Phase::Finalize, "Saving update for the next boot...");
+        let staged = StagedUpdate {
+            version: release.tag_name.clone(),
+            asset: asset.name.clone(),
+            profile: profile.clone(),
+            allow_protected: manifest.allow_protected,
+        };
+        staged.stage(&bytes, platform)?;
+        app_state.set_staged_update(Some(staged));
+        app_state.finish_operation();
+        return Ok(());
+    }
 
-                true
-            },
-            |pr| app_state.update_progress(pr),
-        )?;
+    install_archive(
+        app_state,
+        profile,
+        bytes,
+        &asset.name,
+        &release.tag_name,
+        policy,
+    )
+}
+
+// Install the staged update, e.g. from the boot hook
+pub fn apply_staged_update(app_state: &AppStateManager) -> Result<()> {
+    let staged = app_state.staged_update().ok_or("No staged update")?;
+    let mut phases = vec![
+        Phase::Verify,
+        Phase::Backup,
+        Phase::Extract,
+        Phase::Finalize,
+    ];
+    if staged.profile.reboots() {
+        phases.push(Phase::Reboot);
+    }
+    app_state.start_pipeline(&phases);
+
+    app_state.enter_phase(Phase::Verify, &format!("Verifying {}...", staged.asset));
+    // Left staged, to be tried again once charged
+    battery::check_for_install(app_state.settings().min_battery_percent)?;
+    let bytes = staged.read_archive()?;
+
+    // Unstage before installing, so a broken archive isn't retried on every boot
+    StagedUpdate::discard()?;
+    app_state.set_staged_update(None);
+    verify_zip(&bytes)?;
+
+    let policy = ExtractPolicy {
+        allowed_protected: staged.allow_protected,
+        ..ExtractPolicy::default()
+    };
+    install_archive(
+        app_state,
+        &staged.profile,
+        bytes,
+        &staged.asset,
+        &staged.version,
+        policy,
+    )
+}
+
+fn install_sd_card_archive(app_state: &AppStateManager, archive: &Path) -> Result<()> {
+    // Everything in the archive is installed, like a full update
+    let profile = Profile {
+        name: "SD card".to_string(),
+        description: String::new(),
+        assets: vec![],
+        include: vec![],
+        exclude: vec![],
+        files: vec![],
+        keep_existing_roms: true,
+        post_actions: vec![PostAction::Reboot],
+    };
+    app_state.start_pipeline(&[
+        Phase::Verify,
+        Phase::Backup,
+        Phase::Extract,
+        Phase::Finalize,
+        Phase::Reboot,
+    ]);
+
+    let name = archive
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or("Invalid archive path")?;
+    app_state.enter_phase(Phase::Verify, &format!("Verifying {name}..."));
+    battery::check_for_install(app_state.settings().min_battery_percent)?;
+    let bytes = Bytes::from(std::fs::read(archive)?);
+    verify_zip(&bytes)?;
+
+    // Without a release, the archive's name stands in for its version
+    let version = archive
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    install_archive(
+        app_state,
+        &profile,
+        bytes,
+        &name,
+        &version,
+        ExtractPolicy::default(),
+    )
+}
+
+// Install the verified archive `name` of `version`, then run the profile's post actions
+fn install_archive(
+    app_state: &AppStateManager,
+    profile: &Profile,
+    bytes: Bytes,
+    name: &str,
+    version: &str,
+    mut policy: ExtractPolicy,
+) -> Result<()> {
+    let backup_message = "Checking and backing up installed files...";
+    app_state.enter_phase(Phase::Backup, backup_message);
+    let cancellation = app_state.begin_cancellable();
+
+    let platform = app_state.platform();
+    let filter = profile.file_filter(platform);
+
+    // Work out what the update changes: the files it installs, the ones among them
+    // the user modified since they were installed, and the files dropped upstream
+    let root = PathBuf::from(SDCARD_ROOT);
+    let mut installed_files = InstalledFiles::load();
+    let planned = planned_files(&bytes, &policy, &filter)?;
+    let modified: Vec<PathBuf> = planned
+        .iter()
+        .map(|(path, _)| path)
+        .filter(|path| installed_files.is_modified(&root, path))
+        .cloned()
+        .collect();
+    let dropped: Vec<PathBuf> = installed_files
+        .dropped_files(&planned, &filter)
+        .into_iter()
+        .filter(|path| root.join(path).is_file())
+        .collect();
+
+    // Only ask for confirmation when the update does more than write new files
+    let modified_policy = app_state.settings().modified_files;
+    let needs_choice = !modified.is_empty() && modified_policy == ModifiedFilesPolicy::Prompt;
+    let action = if needs_choice || !dropped.is_empty() {
+        let plan = InstallPlan {
+            install: planned.len(),
+            modified: display_paths(&modified),
+            modified_policy: (!needs_choice).then_some(modified_policy),
+            remove: display_paths(&dropped),
+        };
+        let choice = confirm_install_plan(app_state, plan, &cancellation);
+        app_state.enter_phase(Phase::Backup, backup_message);
+        choice?
     } else {
-        // "Quick" update, just extract MinUI.zip and trimui folder
-        extract_zip(
-            bytes,
-            |file| {
-                ["MinUI.zip", "trimui"]
-                    .iter()
-                    .any(|prefix| file.starts_with(prefix))
-            },
-            |pr| app_state.update_progress(pr),
-        )?;
+        modified_policy
+    };
+    if action == ModifiedFilesPolicy::Keep {
+        policy.keep.extend(modified);
+    } else {
+        policy.backup.extend(modified);
     }
+    policy.remove.clone_from(&dropped);
+    let backups_dir = root.join(UPDATER_DATA_DIR).join("removed");
+    policy.removal_backup_dir = backups_dir.join(version);
+
+    // Extract the update package
+    let report = extract_zip(
+        bytes,
+        &PathBuf::from(SDCARD_ROOT),
+        &policy,
+        filter,
+        |stage, pr| {
+            let phase = app_state.pipeline_progress().map(|p| p.phase);
+            if stage == ExtractStage::Extract && phase != Some(Phase::Extract) {
+                app_state.enter_phase(
+                    Phase::Extract,
+                    &format!("Extracting {name}...\nPlease wait..."),
+                );
+            }
+            app_state.update_progress(pr);
+        },
+        &cancellation,
+    )?;
+    app_state.end_cancellable();
 
     println!("Extraction complete!");
     app_state.set_progress(Some(Progress::Indeterminate));
 
-    app_state.set_current_operation(Some("Update complete, preparing to reboot...".to_string()));
+    prune_backups(&backups_dir, app_state.settings().backups_to_keep);
+
+    // Failing to record the hashes only weakens the next update's detection
+    installed_files.forget(&dropped);
+    installed_files.record(&report.written);
+    if let Err(err) = installed_files.store() {
+        println!("Failed to record installed files: {err}");
+    }
+
+    app_state.enter_phase(
+        Phase::Finalize,
+        &completion_message(&report, profile.reboots()),
+    );
 
     // Give the user a moment to see the completion message
     thread::sleep(std::time::Duration::from_secs(2));
 
-    app_state.set_current_operation(Some("Rebooting system...".to_string()));
+    run_post_actions(app_state, &profile.post_actions)
+}
+
+// Summary shown once an archive is installed
+fn completion_message(report: &ExtractReport, reboots: bool) -> String {
+    let mut message = vec![if reboots {
+        "Update complete, preparing to reboot...".to_string()
+    } else {
+        "Update complete".to_string()
+    }];
+    if !report.kept.is_empty() {
+        message.push(format!("Kept {} modified files", report.kept.len()));
+    }
+    if !report.backed_up.is_empty() {
+        message.push(format!(
+            "Saved {} modified files as .bak",
+            report.backed_up.len()
+        ));
+    }
+    if !report.removed.is_empty() {
+        message.push(format!(
+            "Removed {} files dropped upstream",
+            report.removed.len()
+        ));
+    }
+    if !report.skipped_protected.is_empty() {
+        message.push(format!(
+            "Skipped {} files in Bios, Saves or .userdata",
+            report.skipped_protected.len()
+        ));
+    }
+    if !report.metadata_not_applied.is_empty() {
+        message.push(format!(
+            "Permissions or symlinks of {} files could not be applied (see logs.txt)",
+            report.metadata_not_applied.len()
+        ));
+    }
+    message.join("\n")
+}
+
+// Delete the oldest folders of removed files beyond the `keep` newest
+fn prune_backups(backups_dir: &Path, keep: usize) {
+    let Ok(entries) = std::fs::read_dir(backups_dir) else {
+        return;
+    };
+    let mut backups: Vec<_> = entries
+        .filter_map(std::result::Result::ok)
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .filter(|(_, path)| path.is_dir())
+        .collect();
+    // Newest first
+    backups.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+    for (_, path) in backups.into_iter().skip(keep) {
+        println!("Deleting old backup {}", path.display());
+        if let Err(err) = std::fs::remove_dir_all(&path) {
+            println!("Failed to delete {}: {err}", path.display());
+        }
+    }
+}
+
+fn run_post_actions(app_state: &AppStateManager, actions: &[PostAction]) -> Result<()> {
+    for action in actions {
+        match action {
+            PostAction::Script { path } => {
+                app_state.enter_phase(Phase::Finalize, &format!("Running {path}..."));
+
+                // Scripts on FAT formatted SD cards can't be executable, run them through sh
+                let status = std::process::Command::new("sh")
+                    .arg(PathBuf::from(SDCARD_ROOT).join(path))
+                    .current_dir(SDCARD_ROOT)
+                    .status()?;
+                if !status.success() {
+                    return Err(format!("{path} failed ({status})").into());
+                }
+            }
+            PostAction::Quit => {
+                app_state.finish_operation();
+                app_state.set_should_quit(true);
+                return Ok(());
+            }
+            PostAction::Reboot => {
+                app_state.enter_phase(Phase::Reboot, "Rebooting system...");
+
+                let strategy = app_state.reboot_strategy();
+                strategy.reboot()?;
+                if strategy != RebootStrategy::Noop {
+                    return Ok(());
+                }
+                println!("Reboot skipped");
+            }
+        }
+    }
+
+    // Staying in the updater, so show what is installed now
+    app_state.finish_operation();
+    app_state.set_current_version(InstalledVersion::load());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(tags: &[(&str, bool)], next: Option<&str>) -> Page<Release> {
+        let items = tags
+            .iter()
+            .map(|(tag, prerelease)| {
+                serde_json::from_value(serde_json::json!({
+                    "tag_name": tag,
+                    "prerelease": prerelease,
+                    "assets": [],
+                }))
+                .unwrap()
+            })
+            .collect();
+        Page {
+            items,
+            next: next.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn follows_pages_until_a_stable_release() {
+        let app_state = AppStateManager::new();
+        let fetched = std::cell::RefCell::new(vec![]);
+        let fetch = |url: &str| {
+            fetched.borrow_mut().push(url.to_owned());
+            Ok(match url {
+                "page2" => page(&[("v2-rc", true)], Some("page3")),
+                _ => page(&[("v1", false)], Some("page4")),
+            })
+        };
+
+        let mut releases = page(&[("v3-rc", true)], Some("page2"));
+        extend_until_on_channel(&app_state, &mut releases, fetch).unwrap();
+        let tags: Vec<_> = releases.items.iter().map(|r| r.tag_name.as_str()).collect();
+        assert_eq!(tags, ["v3-rc", "v2-rc", "v1"]);
+        assert_eq!(releases.next.as_deref(), Some("page4"));
+        assert_eq!(fetched.into_inner(), ["page2", "page3"]);
+
+        // Nothing more to fetch once a stable release is listed
+        extend_until_on_channel(&app_state, &mut releases, |_| Err("unexpected".into())).unwrap();
+    }
+
+    #[test]
+    fn refresh_keeps_releases_of_later_pages() {
+        let app_state = AppStateManager::new();
+        let tags = |app_state: &AppStateManager| -> Vec<String> {
+            app_state
+                .nextui_releases_and_tags()
+                .unwrap()
+                .iter()
+                .map(|r| r.release.tag_name.clone())
+                .collect()
+        };
+
+        apply_nextui_releases(&app_state, &page(&[("v3", false)], Some("page2")), true).unwrap();
+        app_state.set_nextui_releases_and_tags(Some(
+            page(&[("v3", false), ("v2", false), ("v1", false)], None)
+                .items
+                .into_iter()
+                .map(|release| ReleaseAndTag { release, tag: None })
+                .collect(),
+        ));
+        app_state.set_nextui_releases_next_page(Some("page3".to_string()));
 
-    // Reboot the system
-    match std::process::Command::new("reboot").output() {
-        Ok(_) => Ok(()),
-        Err(e) => Err(Box::new(e)),
+        apply_nextui_releases(
+            &app_state,
+            &page(&[("v4", false), ("v3", false)], Some("page2")),
+            true,
+        )
+        .unwrap();
+        assert_eq!(tags(&app_state), ["v4", "v3", "v2", "v1"]);
+        assert_eq!(
+            app_state.nextui_releases_next_page().as_deref(),
+            Some("page3")
+        );
     }
 }
@@ -0,0 +1,341 @@
+use std::path::{Path, PathBuf};
+use std::process::{exit, Command};
+use std::thread;
+
+use bytes::Bytes;
+
+use super::ask;
+use super::extract::{extract_zip, ExtractPolicy};
+use super::fetching::fetch_latest_release;
+use super::{download_asset, verify_download};
+use crate::app_state::{
+    AppStateManager, CancellationToken, Cancelled, Confirmation, Phase, Progress,
+};
+use crate::platform::Platform;
+use crate::settings::Settings;
+use crate::Result;
+
+const UPDATER_REPO: &str = "adam-ikari/nextui-updater-pak";
+const UPDATER_BINARY: &str = "nextui-updater";
+// Folder inside the pak the new version is extracted to before it is installed
+const STAGING_DIR: &str = ".self-update";
+// Version the updater restarted into, removed once it started. launch.sh rolls
+// back to the previous binary if it is still there when the updater exits.
+const PENDING_FILE: &str = "self-update.pending";
+// Version launch.sh rolled back from, which isn't installed again
+const FAILED_FILE: &str = "self-update.failed";
+
+// Folder of the running updater, e.g. /mnt/SDCARD/Tools/tg5040/Updater.pak
+fn pak_dir() -> Result<PathBuf> {
+    Ok(std::env::current_exe()?
+        .parent()
+        .ok_or("Can't find the updater's folder")?
+        .to_path_buf())
+}
+
+// Paths of the files called `name` below `dir`
+fn find_files(dir: &Path, name: &str) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let mut found = vec![];
+    for path in entries
+        .filter_map(std::result::Result::ok)
+        .map(|e| e.path())
+    {
+        if path.is_dir() {
+            found.extend(find_files(&path, name));
+        } else if path.file_name().is_some_and(|n| n == name) {
+            found.push(path);
+        }
+    }
+    found
+}
+
+// Folder holding the new updater binary in the extracted package, which may
+// contain the pak itself or the SD card layout (Tools/<platform>/...)
+fn new_pak_root(staging: &Path, platform: Option<Platform>) -> Result<PathBuf> {
+    let binaries = find_files(staging, UPDATER_BINARY);
+    let binary = binaries
+        .iter()
+        .find(|path| platform.is_some_and(|p| path.components().any(|c| c.as_os_str() == p.name())))
+        .or_else(|| binaries.first())
+        .ok_or("Updater binary not found in the package")?;
+
+    Ok(binary.parent().unwrap_or(staging).to_path_buf())
+}
+
+// Make sure the new binary runs and is the expected version before switching to it
+fn check_binary(binary: &Path, version: &semver::Version) -> Result<()> {
+    let output = Command::new(binary).arg("--version").output()?;
+    if !output.status.success() {
+        return Err(format!("New updater failed to run ({})", output.status).into());
+    }
+
+    let reported = String::from_utf8_lossy(&output.stdout);
+    if semver::Version::parse(reported.trim()).ok().as_ref() != Some(version) {
+        return Err(format!("New updater reports version {}", reported.trim()).into());
+    }
+    Ok(())
+}
+
+// Copy the new pak's files over the running one, the binary last so that a
+// failure before leaves the current updater in place
+fn install_pak(new_root: &Path, pak_dir: &Path) -> Result<()> {
+    copy_dir(new_root, pak_dir)?;
+
+    let current_binary = pak_dir.join(UPDATER_BINARY);
+    std::fs::rename(&current_binary, current_binary.with_extension("bak"))?;
+    if let Err(err) = std::fs::rename(new_root.join(UPDATER_BINARY), &current_binary) {
+        std::fs::rename(current_binary.with_extension("bak"), &current_binary)?;
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)?.filter_map(std::result::Result::ok) {
+        let path = entry.path();
+        let target = to.join(entry.file_name());
+        if path.is_dir() {
+            copy_dir(&path, &target)?;
+        } else if entry.file_name() != UPDATER_BINARY {
+            std::fs::copy(&path, &target)?;
+        }
+    }
+    Ok(())
+}
+
+// Extract the new version next to the running updater in `pak_dir`, so it can be
+// checked before switching to it
+fn install_package(
+    bytes: Bytes,
+    pak_dir: &Path,
+    platform: Option<Platform>,
+    version: &semver::Version,
+    progress: impl Fn(f32),
+) -> Result<()> {
+    let staging = pak_dir.join(STAGING_DIR);
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::create_dir_all(&staging)?;
+    let result = extract_zip(
+        bytes,
+        &staging,
+        &ExtractPolicy::default(),
+        |_| true,
+        |_, pr| progress(pr),
+        &CancellationToken::default(),
+    )
+    .and_then(|_| {
+        let new_root = new_pak_root(&staging, platform)?;
+        check_binary(&new_root.join(UPDATER_BINARY), version)?;
+        install_pak(&new_root, pak_dir)
+    });
+    let _ = std::fs::remove_dir_all(&staging);
+    result
+}
+
+// Apply a choice to the settings and remember it for the next launches
+fn remember(app_state: &AppStateManager, choice: impl FnOnce(&mut Settings)) {
+    let mut settings = app_state.settings();
+    choice(&mut settings);
+    if let Err(err) = settings.save() {
+        println!("Failed to save settings: {err}");
+    }
+    app_state.set_settings(settings);
+}
+
+// Ask whether to update the updater, returning whether to go ahead
+fn ask_to_update(
+    app_state: &AppStateManager,
+    available: &semver::Version,
+    installed: &semver::Version,
+) -> Result<bool> {
+    let choice = ask(
+        app_state,
+        Confirmation {
+            message: format!("NextUI Updater {available} is available\n(installed: {installed})"),
+            choices: vec![
+                "Not now".to_string(),
+                "Skip this version".to_string(),
+                "Update".to_string(),
+            ],
+        },
+    );
+
+    match choice {
+        Ok(0) => {
+            remember(app_state, Settings::postpone_self_update);
+            Ok(false)
+        }
+        Ok(1) => {
+            remember(app_state, |settings| {
+                settings.skipped_updater_version = Some(available.to_string());
+            });
+            Ok(false)
+        }
+        Ok(_) => Ok(true),
+        // B only dismisses the question until the next launch
+        Err(err) if err.is::<Cancelled>() => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+pub fn self_update(app_state: &AppStateManager) -> Result<()> {
+    // Fetch latest release information
+    app_state.start_operation("Fetching latest updater release...");
+
+    println!("Fetching latest updater release...");
+
+    let release = fetch_latest_release(UPDATER_REPO)?;
+
+    println!("Latest updater release: {release:?}");
+
+    let available = semver::Version::parse(&release.tag_name)?;
+    let installed = semver::Version::parse(env!("CARGO_PKG_VERSION"))?;
+
+    if available <= installed {
+        println!("No updates available");
+        return Ok(());
+    }
+
+    let pak_dir = pak_dir()?;
+    if std::fs::read_to_string(pak_dir.join(FAILED_FILE))
+        .is_ok_and(|failed| failed.trim() == release.tag_name)
+    {
+        println!("Updater {available} failed to start before, not updating");
+        return Ok(());
+    }
+
+    println!("New version available: {available} (current: {installed})");
+    let settings = app_state.settings();
+    if settings.skipped_updater_version == Some(available.to_string()) {
+        println!("Updater {available} was skipped");
+        return Ok(());
+    }
+    if settings.self_update_postponed() {
+        println!("Updating the updater was postponed");
+        return Ok(());
+    }
+    if !ask_to_update(app_state, &available, &installed)? {
+        return Ok(());
+    }
+
+    app_state.start_pipeline(&[
+        Phase::Download,
+        Phase::Verify,
+        Phase::Extract,
+        Phase::Finalize,
+    ]);
+    app_state.enter_phase(Phase::Download, "Downloading updater...");
+
+    let asset = release
+        .assets
+        .iter()
+        .find(|a| a.name.to_lowercase().ends_with(".pakz"))
+        .ok_or("No .pakz asset found")?;
+
+    let cancellation = app_state.begin_cancellable();
+    let bytes = download_asset(app_state, asset, &cancellation)?;
+    app_state.end_cancellable();
+
+    app_state.enter_phase(Phase::Verify, "Verifying updater download...");
+    verify_download(&bytes, asset)?;
+
+    app_state.enter_phase(
+        Phase::Extract,
+        &format!("Extracting NextUI Updater {}...", release.tag_name),
+    );
+
+    install_package(bytes, &pak_dir, app_state.platform(), &available, |pr| {
+        app_state.update_progress(pr);
+    })?;
+
+    println!("Extraction complete!");
+    app_state.set_progress(Some(Progress::Indeterminate));
+
+    std::fs::write(pak_dir.join(PENDING_FILE), &release.tag_name)?;
+    let _ = std::fs::remove_file(pak_dir.join(FAILED_FILE));
+
+    app_state.enter_phase(
+        Phase::Finalize,
+        "Self-update success! Restarting updater...",
+    );
+
+    // Give the user a moment to see the completion message
+    thread::sleep(std::time::Duration::from_secs(1));
+
+    // "5" is the exit code for "restart required"
+    exit(5);
+}
+
+// The running updater got as far as showing its UI, so the previous binary
+// isn't needed as a fallback anymore
+pub fn confirm_self_update() {
+    let Ok(pak_dir) = pak_dir() else {
+        return;
+    };
+    if std::fs::remove_file(pak_dir.join(PENDING_FILE)).is_ok() {
+        println!("Updated to {}", env!("CARGO_PKG_VERSION"));
+        let _ = std::fs::remove_file(pak_dir.join(UPDATER_BINARY).with_extension("bak"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    // A .pakz holding `launch.sh` and an updater binary reporting `version`
+    fn pakz(version: &str) -> Bytes {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        let options = zip::write::SimpleFileOptions::default().unix_permissions(0o755);
+        zip.start_file("launch.sh", options).unwrap();
+        zip.write_all(b"#!/bin/sh\n").unwrap();
+        zip.start_file(UPDATER_BINARY, options).unwrap();
+        zip.write_all(format!("#!/bin/sh\necho {version}\n").as_bytes())
+            .unwrap();
+        Bytes::from(zip.finish().unwrap().into_inner())
+    }
+
+    fn pak_dir_with_updater() -> tempfile::TempDir {
+        let pak_dir = tempfile::tempdir().unwrap();
+        std::fs::write(pak_dir.path().join(UPDATER_BINARY), "old").unwrap();
+        pak_dir
+    }
+
+    #[test]
+    fn installs_checked_package() {
+        let pak_dir = pak_dir_with_updater();
+        let version = semver::Version::new(1, 2, 3);
+
+        install_package(pakz("1.2.3"), pak_dir.path(), None, &version, |_| {}).unwrap();
+
+        let binary = pak_dir.path().join(UPDATER_BINARY);
+        assert!(std::fs::read_to_string(&binary)
+            .unwrap()
+            .contains("echo 1.2.3"));
+        assert_eq!(
+            std::fs::read_to_string(binary.with_extension("bak")).unwrap(),
+            "old"
+        );
+        assert!(pak_dir.path().join("launch.sh").is_file());
+        assert!(!pak_dir.path().join(STAGING_DIR).exists());
+    }
+
+    #[test]
+    fn keeps_current_updater_on_version_mismatch() {
+        let pak_dir = pak_dir_with_updater();
+        let version = semver::Version::new(1, 2, 3);
+
+        assert!(install_package(pakz("1.2.0"), pak_dir.path(), None, &version, |_| {}).is_err());
+
+        let binary = pak_dir.path().join(UPDATER_BINARY);
+        assert_eq!(std::fs::read_to_string(&binary).unwrap(), "old");
+        assert!(!binary.with_extension("bak").exists());
+        assert!(!pak_dir.path().join(STAGING_DIR).exists());
+    }
+}
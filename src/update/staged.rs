@@ -0,0 +1,138 @@
+use std::path::PathBuf;
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atomic_write::write_atomically, platform::Platform, profiles::Profile, Result, SDCARD_ROOT,
+    UPDATER_DATA_DIR,
+};
+
+// Lines around the boot hook in auto.sh, so it can be found and removed again
+const HOOK_START: &str = "# >>> nextui-updater staged update";
+const HOOK_END: &str = "# <<< nextui-updater staged update";
+
+// An update downloaded and verified now, installed on the next boot by a hook
+// in NextUI's auto.sh running `nextui-updater --apply-staged`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StagedUpdate {
+    // Release tag
+    pub version: String,
+    // Name of the staged archive
+    pub asset: String,
+    pub profile: Profile,
+    #[serde(default)]
+    pub allow_protected: Vec<String>,
+}
+
+fn staged_dir() -> PathBuf {
+    PathBuf::from(SDCARD_ROOT)
+        .join(UPDATER_DATA_DIR)
+        .join("staged")
+}
+
+fn staged_update_path() -> PathBuf {
+    staged_dir().join("staged.json")
+}
+
+// Script NextUI runs at boot on `platform`
+fn auto_sh_path(platform: Platform) -> PathBuf {
+    PathBuf::from(SDCARD_ROOT)
+        .join(".userdata")
+        .join(platform.name())
+        .join("auto.sh")
+}
+
+fn without_hook(script: &str) -> String {
+    let mut in_hook = false;
+    let mut lines = vec![];
+    for line in script.lines() {
+        match line.trim() {
+            HOOK_START => in_hook = true,
+            HOOK_END => in_hook = false,
+            _ if !in_hook => lines.push(line),
+            _ => {}
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+// Add the hook applying the staged update to auto.sh, creating it if needed
+fn install_boot_hook(platform: Platform) -> Result<()> {
+    let pak_dir = std::env::current_exe()?
+        .parent()
+        .ok_or("Can't find the updater's folder")?
+        .to_path_buf();
+    let path = auto_sh_path(platform);
+
+    let script = std::fs::read_to_string(&path).unwrap_or_else(|_| "#!/bin/sh\n".to_string());
+    let hook = format!(
+        "{HOOK_START}\n\
+        if [ -f \"{staged}\" ]; then\n    \
+            (cd \"{pak}\" && ./nextui-updater --apply-staged >> logs.txt 2>&1)\n\
+        fi\n\
+        {HOOK_END}\n",
+        staged = staged_update_path().display(),
+        pak = pak_dir.display(),
+    );
+    write_atomically(&path, (without_hook(&script) + &hook).as_bytes())
+}
+
+fn remove_boot_hooks() -> Result<()> {
+    for platform in Platform::ALL {
+        let path = auto_sh_path(platform);
+        let Ok(script) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        if script.contains(HOOK_START) {
+            write_atomically(&path, without_hook(&script).as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+impl StagedUpdate {
+    pub fn load() -> Option<Self> {
+        let contents = std::fs::read_to_string(staged_update_path()).ok()?;
+        serde_json::from_str(&contents)
+            .map_err(|err| println!("Ignoring invalid staged update: {err}"))
+            .ok()
+    }
+
+    fn archive_path(&self) -> PathBuf {
+        staged_dir().join(&self.asset)
+    }
+
+    // Save the archive and this description, then hook into the next boot
+    pub fn stage(&self, bytes: &Bytes, platform: Option<Platform>) -> Result<()> {
+        let platform = platform.ok_or("Installing later needs a known platform")?;
+
+        // Only one update can be staged
+        Self::discard()?;
+        write_atomically(&self.archive_path(), bytes)?;
+        write_atomically(
+            &staged_update_path(),
+            serde_json::to_string_pretty(self)?.as_bytes(),
+        )?;
+
+        if let Err(err) = install_boot_hook(platform) {
+            // Don't leave a staged update which would never be applied
+            Self::discard()?;
+            return Err(format!("Failed to add the boot hook: {err}").into());
+        }
+        Ok(())
+    }
+
+    pub fn read_archive(&self) -> Result<Bytes> {
+        Ok(Bytes::from(std::fs::read(self.archive_path())?))
+    }
+
+    // Remove the staged update and its boot hook
+    pub fn discard() -> Result<()> {
+        remove_boot_hooks()?;
+        match std::fs::remove_dir_all(staged_dir()) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use std::cmp::Ordering;
+use std::path::PathBuf;
+
+use regex::Regex;
+
+use crate::github::{Release, ReleaseAndTag};
+use crate::SDCARD_ROOT;
+
+// Installed NextUI version, as described by `.system/version.txt`:
+//
+//     NextUI-20250412-1
+//     1a2b3c4
+//
+// The first line is the release name, the second the commit the build was made
+// from. Newer builds may append further lines, such as the platform.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstalledVersion {
+    pub name: String,
+    // Abbreviated or full commit hash, None if missing or malformed
+    pub commit: Option<String>,
+    // Build date (YYYY-MM-DD) taken from the release name
+    pub build_date: Option<String>,
+    pub platform: Option<String>,
+}
+
+fn is_commit_hash(s: &str) -> bool {
+    (7..=40).contains(&s.len()) && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+impl InstalledVersion {
+    pub fn parse(contents: &str) -> Option<Self> {
+        let mut lines = contents.lines().map(str::trim).filter(|l| !l.is_empty());
+
+        let name = lines.next()?.to_owned();
+
+        let commit = lines.next().and_then(|line| {
+            if is_commit_hash(line) {
+                Some(line.to_ascii_lowercase())
+            } else {
+                println!("Ignoring malformed commit hash in version.txt: {line:?}");
+                None
+            }
+        });
+
+        let date_re = Regex::new(r"(?:^|-)(?<y>\d{4})(?<m>\d{2})(?<d>\d{2})(?:-|$)")
+            .expect("Failed to compile regex");
+        let build_date = date_re
+            .captures(&name)
+            .map(|c| format!("{}-{}-{}", &c["y"], &c["m"], &c["d"]));
+
+        let platform = lines
+            .map(|line| line.strip_prefix("platform=").unwrap_or(line))
+            .find(|line| line.chars().all(|c| c.is_ascii_alphanumeric()))
+            .map(ToOwned::to_owned);
+
+        Some(Self {
+            name,
+            commit,
+            build_date,
+            platform,
+        })
+    }
+
+    pub fn load() -> Option<Self> {
+        let path = PathBuf::from(SDCARD_ROOT).join(".system/version.txt");
+        let contents = std::fs::read_to_string(&path)
+            .inspect_err(|err| println!("Failed to read {}: {err}", path.display()))
+            .ok()?;
+
+        let version = Self::parse(&contents);
+        println!("Installed version: {version:?}");
+        version
+    }
+
+    // Whether this is the build of the given (full) commit SHA.
+    // An unknown commit never matches.
+    pub fn is_commit(&self, sha: &str) -> bool {
+        self.commit
+            .as_deref()
+            .is_some_and(|commit| sha.to_ascii_lowercase().starts_with(commit))
+    }
+}
+
+// Comparable version parsed from a NextUI tag or release name. Versions of
+// different schemes can't be compared.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NextUIVersion {
+    // NextUI-20250412-1 style build names: build date, then build number
+    Dated { date: u32, build: u32 },
+    // v6.2.1 style tags, also accepting "v6.2"
+    Semantic(semver::Version),
+}
+
+impl NextUIVersion {
+    pub fn parse(name: &str) -> Option<Self> {
+        let trimmed = name.trim();
+        let trimmed = trimmed
+            .strip_prefix("NextUI-")
+            .or_else(|| trimmed.strip_prefix("nextui-"))
+            .unwrap_or(trimmed);
+
+        let dated_re =
+            Regex::new(r"^(?<date>\d{8})(?:-(?<build>\d+))?$").expect("Failed to compile regex");
+        if let Some(captures) = dated_re.captures(trimmed) {
+            return Some(Self::Dated {
+                date: captures["date"].parse().ok()?,
+                build: captures
+                    .name("build")
+                    .map_or(Some(0), |b| b.as_str().parse().ok())?,
+            });
+        }
+
+        let version = trimmed
+            .strip_prefix('v')
+            .or_else(|| trimmed.strip_prefix('V'))
+            .unwrap_or(trimmed);
+        // Pad "6" and "6.2" to a full major.minor.patch triple
+        let (core, suffix) = version
+            .find(['-', '+'])
+            .map_or((version, ""), |i| version.split_at(i));
+        let padding = match core.split('.').count() {
+            1 => ".0.0",
+            2 => ".0",
+            _ => "",
+        };
+        semver::Version::parse(&format!("{core}{padding}{suffix}"))
+            .ok()
+            .map(Self::Semantic)
+    }
+}
+
+impl PartialOrd for NextUIVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Dated { date, build }, Self::Dated { date: d, build: b }) => {
+                Some((date, build).cmp(&(d, b)))
+            }
+            (Self::Semantic(a), Self::Semantic(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+// Compare releases by version when both tags use the same scheme, otherwise
+// (or for equal versions) by publish date
+pub fn compare_releases(a: &Release, b: &Release) -> Ordering {
+    let by_version = NextUIVersion::parse(&a.tag_name)
+        .zip(NextUIVersion::parse(&b.tag_name))
+        .and_then(|(a, b)| a.partial_cmp(&b))
+        .unwrap_or(Ordering::Equal);
+    by_version.then_with(|| a.published_at.cmp(&b.published_at))
+}
+
+// Sort releases newest first. Releases are ordered by publish date, then the
+// releases of each versioning scheme are put in version order among the places
+// they take, so mixed and unparsed tags keep their place by date.
+pub fn sort_releases(releases_and_tags: &mut [ReleaseAndTag]) {
+    releases_and_tags.sort_by(|a, b| b.release.published_at.cmp(&a.release.published_at));
+
+    let versions: Vec<Option<NextUIVersion>> = releases_and_tags
+        .iter()
+        .map(|r| NextUIVersion::parse(&r.release.tag_name))
+        .collect();
+    let schemes: [fn(&NextUIVersion) -> bool; 2] = [
+        |v| matches!(v, NextUIVersion::Dated { .. }),
+        |v| matches!(v, NextUIVersion::Semantic(_)),
+    ];
+    for is_scheme in schemes {
+        let places: Vec<usize> = (0..versions.len())
+            .filter(|&i| versions[i].as_ref().is_some_and(is_scheme))
+            .collect();
+        let mut ordered = places.clone();
+        ordered.sort_by(|&a, &b| {
+            versions[b]
+                .partial_cmp(&versions[a])
+                .unwrap_or(Ordering::Equal)
+        });
+        let entries: Vec<ReleaseAndTag> = ordered
+            .iter()
+            .map(|&i| releases_and_tags[i].clone())
+            .collect();
+        for (place, entry) in places.into_iter().zip(entries) {
+            releases_and_tags[place] = entry;
+        }
+    }
+}
+
+// Whether `target` is older than the installed version, None if unknown
+pub fn is_older_than_installed(
+    target: &Release,
+    installed: &InstalledVersion,
+    releases_and_tags: &[ReleaseAndTag],
+) -> Option<bool> {
+    // Prefer the release the installed commit belongs to
+    let installed_release = releases_and_tags.iter().find(|r| {
+        r.tag
+            .as_ref()
+            .is_some_and(|tag| installed.is_commit(&tag.commit.sha))
+    });
+    if let Some(installed_release) = installed_release {
+        return Some(compare_releases(target, &installed_release.release) == Ordering::Less);
+    }
+
+    // Otherwise compare against the installed release name, if it uses the same scheme
+    NextUIVersion::parse(&target.tag_name)?
+        .partial_cmp(&NextUIVersion::parse(&installed.name)?)
+        .map(|ordering| ordering == Ordering::Less)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_installed_version() {
+        let version = InstalledVersion::parse("NextUI-20250412-1\n1A2B3C4\ntg5040\n").unwrap();
+        assert_eq!(version.name, "NextUI-20250412-1");
+        assert_eq!(version.commit.as_deref(), Some("1a2b3c4"));
+        assert_eq!(version.build_date.as_deref(), Some("2025-04-12"));
+        assert_eq!(version.platform.as_deref(), Some("tg5040"));
+    }
+
+    #[test]
+    fn parses_platform_key_and_skips_blank_lines() {
+        let version = InstalledVersion::parse(
+            "\r\n  NextUI-20250601  \r\n\r\n1a2b3c4d\r\nplatform=my355\r\n",
+        )
+        .unwrap();
+        assert_eq!(version.name, "NextUI-20250601");
+        assert_eq!(version.commit.as_deref(), Some("1a2b3c4d"));
+        assert_eq!(version.build_date.as_deref(), Some("2025-06-01"));
+        assert_eq!(version.platform.as_deref(), Some("my355"));
+    }
+
+    #[test]
+    fn ignores_malformed_commit_and_missing_fields() {
+        let version = InstalledVersion::parse("v6.2.1\nnot-a-commit\n").unwrap();
+        assert_eq!(version.commit, None);
+        assert_eq!(version.build_date, None);
+        assert_eq!(version.platform, None);
+
+        // Too short to be an abbreviated hash
+        let version = InstalledVersion::parse("NextUI-20250412-1\n1a2b3c\n").unwrap();
+        assert_eq!(version.commit, None);
+
+        assert_eq!(InstalledVersion::parse(" \n\n"), None);
+    }
+
+    #[test]
+    fn matches_full_commit_by_prefix() {
+        let version = InstalledVersion::parse("NextUI-20250412-1\n1a2b3c4\n").unwrap();
+        assert!(version.is_commit("1A2B3C4D5E6F7A8B9C0D1E2F3A4B5C6D7E8F9A0B"));
+        assert!(!version.is_commit("0a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b"));
+
+        let unknown = InstalledVersion::parse("NextUI-20250412-1\n").unwrap();
+        assert!(!unknown.is_commit("1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b"));
+    }
+
+    fn dated(date: u32, build: u32) -> NextUIVersion {
+        NextUIVersion::Dated { date, build }
+    }
+
+    fn semantic(version: &str) -> NextUIVersion {
+        NextUIVersion::Semantic(semver::Version::parse(version).unwrap())
+    }
+
+    #[test]
+    fn parses_dated_names() {
+        assert_eq!(
+            NextUIVersion::parse("NextUI-20250412-1"),
+            Some(dated(20_250_412, 1))
+        );
+        assert_eq!(
+            NextUIVersion::parse("nextui-20250412-12"),
+            Some(dated(20_250_412, 12))
+        );
+        assert_eq!(
+            NextUIVersion::parse(" 20250412 "),
+            Some(dated(20_250_412, 0))
+        );
+        assert_eq!(NextUIVersion::parse("NextUI-nightly"), None);
+    }
+
+    #[test]
+    fn parses_and_pads_semantic_tags() {
+        assert_eq!(NextUIVersion::parse("v6.2.1"), Some(semantic("6.2.1")));
+        assert_eq!(NextUIVersion::parse("V6.2"), Some(semantic("6.2.0")));
+        assert_eq!(NextUIVersion::parse("6"), Some(semantic("6.0.0")));
+        assert_eq!(
+            NextUIVersion::parse("v6.2-beta.1"),
+            Some(semantic("6.2.0-beta.1"))
+        );
+        assert_eq!(NextUIVersion::parse("latest"), None);
+        assert_eq!(NextUIVersion::parse(""), None);
+    }
+
+    #[test]
+    fn orders_versions() {
+        assert!(dated(20_250_412, 1) < dated(20_250_412, 2));
+        assert!(dated(20_250_412, 9) < dated(20_250_413, 0));
+        assert!(semantic("6.2.0-beta.1") < semantic("6.2.0"));
+        assert!(semantic("6.2.0") < semantic("6.10.0"));
+        // Versions of different schemes aren't comparable
+        assert_eq!(dated(20_250_412, 0).partial_cmp(&semantic("6.0.0")), None);
+    }
+
+    fn release(tag: &str, published_at: &str) -> ReleaseAndTag {
+        ReleaseAndTag {
+            release: serde_json::from_value(serde_json::json!({
+                "tag_name": tag,
+                "published_at": published_at,
+                "assets": [],
+            }))
+            .unwrap(),
+            tag: None,
+        }
+    }
+
+    fn sorted(releases: &[(&str, &str)]) -> Vec<String> {
+        let mut releases: Vec<_> = releases.iter().map(|(t, p)| release(t, p)).collect();
+        sort_releases(&mut releases);
+        releases.into_iter().map(|r| r.release.tag_name).collect()
+    }
+
+    #[test]
+    fn sorts_by_version_within_a_scheme() {
+        // A fix for an older build published after a newer one
+        let tags = sorted(&[
+            ("NextUI-20250412-1", "2025-04-12T00:00:00Z"),
+            ("NextUI-20250301-2", "2025-04-20T00:00:00Z"),
+            ("NextUI-20250510-0", "2025-05-10T00:00:00Z"),
+        ]);
+        assert_eq!(
+            tags,
+            [
+                "NextUI-20250510-0",
+                "NextUI-20250412-1",
+                "NextUI-20250301-2"
+            ]
+        );
+    }
+
+    #[test]
+    fn sorts_mixed_and_unparsed_tags_by_publish_date() {
+        let tags = sorted(&[
+            ("v6.1.0", "2025-01-01T00:00:00Z"),
+            ("NextUI-20250412-1", "2025-04-12T00:00:00Z"),
+            ("nightly", "2025-05-01T00:00:00Z"),
+            ("v6.0.0", "2025-06-01T00:00:00Z"),
+        ]);
+        // The semantic tags swap places, the others keep theirs
+        assert_eq!(tags, ["v6.1.0", "nightly", "NextUI-20250412-1", "v6.0.0"]);
+
+        let older = release("NextUI-20250412-1", "2025-04-12T00:00:00Z").release;
+        let newer = release("v6.0.0", "2025-06-01T00:00:00Z").release;
+        assert_eq!(compare_releases(&older, &newer), Ordering::Less);
+        assert_eq!(compare_releases(&newer, &older), Ordering::Greater);
+    }
+}